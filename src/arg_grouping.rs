@@ -0,0 +1,177 @@
+use std::ffi::OsStr;
+
+use crate::{ArgGrouping, CommandSchema};
+
+const END_OF_OPTIONS: &str = "--";
+
+fn starts_with_dash(arg: &OsStr) -> bool {
+    arg.as_encoded_bytes().first() == Some(&b'-')
+}
+
+/// Whether `arg` looks like a flag that takes its value from the next arg.
+fn is_flag_without_value(arg: &OsStr) -> bool {
+    starts_with_dash(arg) && arg != "-" && !arg.as_encoded_bytes().contains(&b'=')
+}
+
+/// Splits the `args` of `program` into groups, for args that were not added
+/// in groups.
+pub(crate) fn group_args<'a>(
+    program: &OsStr,
+    args: &[&'a OsStr],
+    arg_grouping: ArgGrouping,
+) -> Vec<Vec<&'a OsStr>> {
+    match arg_grouping {
+        ArgGrouping::Separate => args.iter().map(|arg| vec![*arg]).collect(),
+        ArgGrouping::Heuristic => infer_arg_groups(args, None),
+        ArgGrouping::Schema => infer_arg_groups(args, CommandSchema::for_program(program).as_ref()),
+    }
+}
+
+/// See [`ArgGrouping::Heuristic`] and [`ArgGrouping::Schema`].
+pub(crate) fn infer_arg_groups<'a>(
+    args: &[&'a OsStr],
+    schema: Option<&CommandSchema>,
+) -> Vec<Vec<&'a OsStr>> {
+    let mut groups: Vec<Vec<&OsStr>> = vec![];
+    let mut args = args.iter().copied().peekable();
+    while let Some(arg) = args.next() {
+        if arg == END_OF_OPTIONS {
+            groups.push(vec![arg]);
+            groups.extend(args.by_ref().map(|arg| vec![arg]));
+            break;
+        }
+        let known_takes_value = schema.and_then(|schema| schema.takes_value(arg.to_str()?));
+        match args.peek() {
+            Some(next)
+                if *next != END_OF_OPTIONS
+                    && known_takes_value.unwrap_or_else(|| {
+                        is_flag_without_value(arg) && !starts_with_dash(next)
+                    }) =>
+            {
+                groups.push(vec![arg, *next]);
+                args.next();
+            }
+            _ => groups.push(vec![arg]),
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::group_args;
+    use crate::ArgGrouping;
+
+    fn group(program: &str, args: &[&str], arg_grouping: ArgGrouping) -> Vec<Vec<String>> {
+        let args: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+        group_args(OsStr::new(program), &args, arg_grouping)
+            .into_iter()
+            .map(|group| {
+                group
+                    .into_iter()
+                    .map(|arg| arg.to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn infer(args: &[&str]) -> Vec<Vec<String>> {
+        group("example", args, ArgGrouping::Heuristic)
+    }
+
+    #[test]
+    fn heuristic() {
+        assert_eq!(
+            infer(&["--exclude", ".git", "-v", "--delete", "src/", "dest/"]),
+            [
+                vec!["--exclude", ".git"],
+                vec!["-v"],
+                vec!["--delete", "src/"],
+                vec!["dest/"]
+            ]
+        );
+        assert_eq!(
+            infer(&["--color=always", "main", "-", "x", "-n", "-5"]),
+            [
+                vec!["--color=always"],
+                vec!["main"],
+                vec!["-"],
+                vec!["x"],
+                vec!["-n"],
+                vec!["-5"]
+            ]
+        );
+        assert_eq!(
+            infer(&["-m", "msg", "--", "-f", "file"]),
+            [vec!["-m", "msg"], vec!["--"], vec!["-f"], vec!["file"]]
+        );
+        assert_eq!(infer(&["-o", "--"]), [vec!["-o"], vec!["--"]]);
+    }
+
+    #[test]
+    fn schema() {
+        assert_eq!(
+            group(
+                "tar",
+                &["-czf", "out.tar.gz", "-C", "./dist", "-v", "."],
+                ArgGrouping::Schema
+            ),
+            [
+                vec!["-czf", "out.tar.gz"],
+                vec!["-C", "./dist"],
+                vec!["-v"],
+                vec!["."]
+            ]
+        );
+        assert_eq!(
+            group(
+                "/usr/bin/git",
+                &[
+                    "log",
+                    "--oneline",
+                    "main",
+                    "--since",
+                    "-1.week",
+                    "--unknown",
+                    "x"
+                ],
+                ArgGrouping::Schema
+            ),
+            [
+                vec!["log"],
+                vec!["--oneline"],
+                vec!["main"],
+                vec!["--since", "-1.week"],
+                vec!["--unknown", "x"]
+            ]
+        );
+        assert_eq!(
+            group("docker", &["build", "-t", "tag", "."], ArgGrouping::Schema),
+            [vec!["build"], vec!["-t", "tag"], vec!["."]]
+        );
+        assert_eq!(
+            group(
+                "docker",
+                &["run", "--rm", "-t", "-e", "A=1", "alpine"],
+                ArgGrouping::Schema
+            ),
+            [
+                vec!["run"],
+                vec!["--rm"],
+                vec!["-t"],
+                vec!["-e", "A=1"],
+                vec!["alpine"]
+            ]
+        );
+        assert_eq!(
+            group("example", &["--level", "3"], ArgGrouping::Schema),
+            [vec!["--level", "3"]]
+        );
+        assert_eq!(
+            group("example", &["--level", "3"], ArgGrouping::Separate),
+            [vec!["--level"], vec!["3"]]
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::{
+    env,
+    error::Error,
+    ffi::{OsStr, OsString},
+    fmt::Display,
+    io,
+    mem::size_of,
+    process::ExitStatus,
+};
+
+use crate::{CommandRunner, PrintableShellCommand, ShellPrintable};
+
+/// Space to leave below `ARG_MAX` when batching, as recommended for `xargs` by
+/// POSIX (in case the environment changes before the command is run).
+const ARG_MAX_HEADROOM: usize = 2048;
+
+/// The maximum combined size of the args and environment of a new process, as
+/// reported by `sysconf(_SC_ARG_MAX)`. `None` if the limit is unknown or
+/// unlimited.
+#[cfg(unix)]
+pub fn arg_max() -> Option<usize> {
+    // SAFETY: `sysconf` has no preconditions.
+    let arg_max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    if arg_max > 0 {
+        Some(arg_max as usize)
+    } else {
+        None
+    }
+}
+
+/// The maximum combined size of the args and environment of a new process.
+/// Always `None` on this platform.
+///
+/// (On Windows, the command line is limited to 32,767 UTF-16 code units
+/// instead. This is not checked.)
+#[cfg(not(unix))]
+pub fn arg_max() -> Option<usize> {
+    None
+}
+
+/// The maximum size of a single arg or env var (`KEY=value`), including its
+/// trailing NUL. Linux enforces this (`MAX_ARG_STRLEN`, 32 pages) in addition
+/// to `ARG_MAX`.
+#[cfg(target_os = "linux")]
+fn max_arg_strlen() -> Option<usize> {
+    // SAFETY: `sysconf` has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size > 0 {
+        Some(32 * page_size as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn max_arg_strlen() -> Option<usize> {
+    None
+}
+
+/// The space that a string takes up in `argv` or `envp`: its bytes, a trailing
+/// NUL, and a pointer.
+fn exec_size(s: &OsStr) -> usize {
+    s.len() + 1 + size_of::<usize>()
+}
+
+/// The environment that a command will receive, taking into account env vars
+/// that were set or removed for the command (and `env_clear()`).
+fn env_vars(command: &PrintableShellCommand) -> Vec<(OsString, OsString)> {
+    let mut vars: Vec<(OsString, Option<OsString>)> = if command.is_env_cleared() {
+        vec![]
+    } else {
+        env::vars_os()
+            .map(|(key, value)| (key, Some(value)))
+            .collect()
+    };
+    for (key, value) in command.get_envs() {
+        match vars
+            .iter_mut()
+            .find(|(existing_key, _)| existing_key == key)
+        {
+            Some((_, existing_value)) => *existing_value = value.map(OsStr::to_owned),
+            None => vars.push((key.to_owned(), value.map(OsStr::to_owned))),
+        }
+    }
+    vars.into_iter()
+        .filter_map(|(key, value)| Some((key, value?)))
+        .collect()
+}
+
+/// The lengths (in bytes, without a trailing NUL) of the program, args, and
+/// env vars (`KEY=value`) that a command will receive.
+fn string_lengths(command: &PrintableShellCommand) -> impl Iterator<Item = usize> + '_ {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(OsStr::len)
+        .chain(
+            env_vars(command)
+                .into_iter()
+                .map(|(key, value)| key.len() + 1 + value.len()),
+        )
+}
+
+/// A command is too long to be run (which would fail with `E2BIG`).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArgMaxExceededError {
+    /// The args and environment together exceed `ARG_MAX` (or the limit
+    /// passed to `split_trailing_group_into_batches_with_limit(…)`).
+    TotalSize {
+        /// The estimated size of the args and environment, in bytes.
+        size: usize,
+        arg_max: usize,
+    },
+    /// A single arg or env var exceeds `MAX_ARG_STRLEN` (Linux only).
+    StringLength {
+        /// The length of the arg or env var (`KEY=value`), in bytes.
+        length: usize,
+        /// The limit, in bytes (including the trailing NUL).
+        max_arg_strlen: usize,
+    },
+}
+
+impl Display for ArgMaxExceededError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArgMaxExceededError::TotalSize { size, arg_max } => write!(
+                f,
+                "Command args and environment ({} bytes) exceed ARG_MAX ({} bytes)",
+                size, arg_max
+            ),
+            ArgMaxExceededError::StringLength {
+                length,
+                max_arg_strlen,
+            } => write!(
+                f,
+                "Command arg or env var ({} bytes) exceeds MAX_ARG_STRLEN ({} bytes)",
+                length, max_arg_strlen
+            ),
+        }
+    }
+}
+
+impl Error for ArgMaxExceededError {}
+
+impl PrintableShellCommand {
+    /// The estimated number of bytes that the program, args, and environment
+    /// take up when the command is run (counted the way Linux counts them for
+    /// `ARG_MAX`).
+    pub fn exec_size(&self) -> usize {
+        std::iter::once(self.get_program())
+            .chain(self.get_args())
+            .map(exec_size)
+            .sum::<usize>()
+            + env_vars(self)
+                .iter()
+                .map(|(key, value)| key.len() + 1 + exec_size(value))
+                .sum::<usize>()
+    }
+
+    /// Returns an error if the command is too long to run: if the args and
+    /// environment exceed `ARG_MAX` (see `arg_max()`), or (on Linux) if a
+    /// single arg or env var exceeds `MAX_ARG_STRLEN`. No limits are checked
+    /// on Windows.
+    pub fn check_arg_max(&self) -> Result<(), ArgMaxExceededError> {
+        self.check_max_arg_strlen()?;
+        match arg_max() {
+            Some(arg_max) => self.check_total_size(arg_max),
+            None => Ok(()),
+        }
+    }
+
+    fn check_total_size(&self, arg_max: usize) -> Result<(), ArgMaxExceededError> {
+        let size = self.exec_size();
+        if size > arg_max {
+            return Err(ArgMaxExceededError::TotalSize { size, arg_max });
+        }
+        Ok(())
+    }
+
+    fn check_max_arg_strlen(&self) -> Result<(), ArgMaxExceededError> {
+        let Some(max_arg_strlen) = max_arg_strlen() else {
+            return Ok(());
+        };
+        match string_lengths(self).find(|length| length + 1 > max_arg_strlen) {
+            Some(length) => Err(ArgMaxExceededError::StringLength {
+                length,
+                max_arg_strlen,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Splits the last arg group into batches (like `xargs`), returning one
+    /// command per batch. Each command has the other arg groups, working
+    /// directory, and env vars of this command, and stays under `ARG_MAX`
+    /// (unless a single arg is too long by itself). If the limit is unknown,
+    /// this returns a single command. Args that exceed `MAX_ARG_STRLEN` cannot
+    /// be split, and are not checked here (see `check_arg_max()`).
+    ///
+    /// This returns an error if the command is too long even without the last
+    /// arg group (e.g. because of a large environment), since no batch could
+    /// be run.
+    pub fn split_trailing_group_into_batches(
+        &mut self,
+    ) -> Result<Vec<PrintableShellCommand>, ArgMaxExceededError> {
+        let limit = arg_max().map(|arg_max| arg_max.saturating_sub(ARG_MAX_HEADROOM));
+        self.split_trailing_group_into_batches_with_limit(limit.unwrap_or(usize::MAX))
+    }
+
+    /// Like `split_trailing_group_into_batches()`, but with each batch limited
+    /// to `limit` bytes (as computed by `exec_size()`).
+    pub fn split_trailing_group_into_batches_with_limit(
+        &mut self,
+        limit: usize,
+    ) -> Result<Vec<PrintableShellCommand>, ArgMaxExceededError> {
+        self.adopt_args();
+        let trailing_args = self.trailing_arg_group().to_vec();
+        let fixed_size = self.with_trailing_arg_group(vec![]).exec_size();
+        if fixed_size > limit {
+            return Err(ArgMaxExceededError::TotalSize {
+                size: fixed_size,
+                arg_max: limit,
+            });
+        }
+        let mut batches: Vec<Vec<OsString>> = vec![];
+        let mut batch: Vec<OsString> = vec![];
+        let mut batch_size = fixed_size;
+        for arg in trailing_args {
+            let arg_size = exec_size(&arg);
+            if !batch.is_empty() && batch_size + arg_size > limit {
+                batches.push(std::mem::take(&mut batch));
+                batch_size = fixed_size;
+            }
+            batch_size += arg_size;
+            batch.push(arg);
+        }
+        if !batch.is_empty() || batches.is_empty() {
+            batches.push(batch);
+        }
+        Ok(batches
+            .into_iter()
+            .map(|batch| self.with_trailing_arg_group(batch))
+            .collect())
+    }
+
+    /// Prints and runs each batch from `split_trailing_group_into_batches()`
+    /// using `runner`, stopping after the first batch that fails. Returns the
+    /// status of the last batch that was run.
+    ///
+    /// If an arg or env var exceeds `MAX_ARG_STRLEN` (on Linux), or if
+    /// `split_trailing_group_into_batches()` returns an error, this returns an
+    /// error without running any batch.
+    pub fn run_in_batches(&mut self, runner: &dyn CommandRunner) -> io::Result<ExitStatus> {
+        let limit = arg_max().map(|arg_max| arg_max.saturating_sub(ARG_MAX_HEADROOM));
+        self.run_in_batches_with_limit(runner, limit.unwrap_or(usize::MAX))
+    }
+
+    fn run_in_batches_with_limit(
+        &mut self,
+        runner: &dyn CommandRunner,
+        limit: usize,
+    ) -> io::Result<ExitStatus> {
+        let to_io_error = |error| io::Error::new(io::ErrorKind::InvalidInput, error);
+        self.check_max_arg_strlen().map_err(to_io_error)?;
+        let batches = self
+            .split_trailing_group_into_batches_with_limit(limit)
+            .map_err(to_io_error)?;
+        let mut status = None;
+        for mut batch in batches {
+            batch.print_invocation_lossy();
+            let batch_status = runner.status(&mut batch)?;
+            status = Some(batch_status);
+            if !batch_status.success() {
+                break;
+            }
+        }
+        Ok(status.expect("There is always at least one batch."))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, io};
+
+    use crate::{
+        ArgMaxExceededError, FakeCommandRunner, FormattingOptions, PrintableShellCommand,
+        ShellPrintableWithOptions,
+    };
+
+    fn rm_command_for_testing() -> PrintableShellCommand {
+        let mut printable_shell_command = PrintableShellCommand::new("rm");
+        printable_shell_command
+            .arg("-f")
+            .args_with_comment((0..10).map(|i| format!("file{}.txt", i)), "generated");
+        printable_shell_command.current_dir("/tmp");
+        printable_shell_command
+    }
+
+    fn fixed_size(printable_shell_command: &PrintableShellCommand) -> usize {
+        printable_shell_command
+            .with_trailing_arg_group(vec![])
+            .exec_size()
+    }
+
+    #[test]
+    fn batches() -> Result<(), Box<dyn Error>> {
+        let mut printable_shell_command = rm_command_for_testing();
+        let arg_size = "file0.txt".len() + 1 + size_of::<usize>();
+        let limit = fixed_size(&printable_shell_command) + 4 * arg_size;
+        let batches =
+            printable_shell_command.split_trailing_group_into_batches_with_limit(limit)?;
+        let printed = batches
+            .iter()
+            .map(|batch| {
+                batch.printable_invocation_string_with_options(FormattingOptions::compact())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            printed,
+            [
+                "rm -f file0.txt file1.txt file2.txt file3.txt `# generated`",
+                "rm -f file4.txt file5.txt file6.txt file7.txt `# generated`",
+                "rm -f file8.txt file9.txt `# generated`",
+            ]
+        );
+        assert!(batches
+            .iter()
+            .all(|batch| batch.get_current_dir() == Some("/tmp".as_ref())));
+
+        // Args that are too long by themselves get their own batch.
+        let mut printable_shell_command = rm_command_for_testing();
+        let limit = fixed_size(&printable_shell_command) + 1;
+        assert_eq!(
+            printable_shell_command
+                .split_trailing_group_into_batches_with_limit(limit)?
+                .len(),
+            10
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_size_over_limit() {
+        let mut printable_shell_command = rm_command_for_testing();
+        let size = fixed_size(&printable_shell_command);
+        assert_eq!(
+            printable_shell_command
+                .split_trailing_group_into_batches_with_limit(size - 1)
+                .err(),
+            Some(ArgMaxExceededError::TotalSize {
+                size,
+                arg_max: size - 1
+            })
+        );
+    }
+
+    #[test]
+    fn arg_max_check() -> Result<(), ArgMaxExceededError> {
+        let mut printable_shell_command = rm_command_for_testing();
+        assert!(printable_shell_command.check_arg_max().is_ok());
+        assert_eq!(
+            printable_shell_command
+                .split_trailing_group_into_batches()?
+                .len(),
+            1
+        );
+        let size = printable_shell_command.exec_size();
+        assert!(printable_shell_command.check_total_size(size).is_ok());
+        assert_eq!(
+            printable_shell_command.check_total_size(size - 1),
+            Err(ArgMaxExceededError::TotalSize {
+                size,
+                arg_max: size - 1
+            })
+        );
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn max_arg_strlen_check() {
+        let max_arg_strlen = super::max_arg_strlen().unwrap();
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.arg("x".repeat(max_arg_strlen - 1));
+        assert!(printable_shell_command.check_arg_max().is_ok());
+
+        printable_shell_command.arg("x".repeat(max_arg_strlen));
+        assert_eq!(
+            printable_shell_command.check_arg_max(),
+            Err(ArgMaxExceededError::StringLength {
+                length: max_arg_strlen,
+                max_arg_strlen
+            })
+        );
+        let runner = FakeCommandRunner::new();
+        assert!(printable_shell_command.run_in_batches(&runner).is_err());
+        assert_eq!(runner.invocations().len(), 0);
+
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.env("HUGE", "x".repeat(max_arg_strlen));
+        assert!(matches!(
+            printable_shell_command.check_arg_max(),
+            Err(ArgMaxExceededError::StringLength { .. })
+        ));
+    }
+
+    #[test]
+    fn run_in_batches() -> io::Result<()> {
+        let runner = || {
+            let mut runner = FakeCommandRunner::new();
+            runner
+                .respond_with(["rm", "-f", "file0.txt", "**"], 0, "", "")
+                .respond_with(["rm", "**"], 1, "", "");
+            runner
+        };
+        let mut printable_shell_command = rm_command_for_testing();
+        let all_at_once = runner();
+        assert_eq!(
+            printable_shell_command.run_in_batches(&all_at_once)?.code(),
+            Some(0)
+        );
+        assert_eq!(all_at_once.invocations().len(), 1);
+
+        // Each arg gets its own batch, and the second batch fails.
+        let one_by_one = runner();
+        let limit = fixed_size(&printable_shell_command) + 1;
+        assert_eq!(
+            printable_shell_command
+                .run_in_batches_with_limit(&one_by_one, limit)?
+                .code(),
+            Some(1)
+        );
+        assert_eq!(one_by_one.invocations().len(), 2);
+
+        // The environment alone exceeds the limit, so no batch is run.
+        let none = runner();
+        printable_shell_command.env("LARGE", "x".repeat(limit));
+        let error = printable_shell_command
+            .run_in_batches_with_limit(&none, limit)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(none.invocations().len(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+/// Builds a `PrintableShellCommand`:
+///
+/// ```
+/// use printable_shell_command::{cmd, ShellPrintable};
+///
+/// let input = std::path::Path::new("./test/My video.mp4");
+/// let extra_filters = ["-filter:a", "atempo=0.5"];
+/// let overwrite = true;
+/// let command = cmd!(
+///     "ffmpeg",
+///     ["-i", input],
+///     ["-filter:v", "setpts=2.0*PTS"],
+///     if overwrite => ["-y"],
+///     ..extra_filters,
+///     "./test/My video (slow-mo).mov",
+/// );
+/// assert_eq!(
+///     command.printable_invocation_string().unwrap(),
+///     "ffmpeg \\
+///   -i './test/My video.mp4' \\
+///   -filter:v 'setpts=2.0*PTS' \\
+///   -y \\
+///   -filter:a \\
+///   atempo=0.5 \\
+///   './test/My video (slow-mo).mov'"
+/// );
+/// ```
+///
+/// - `[a, b, …]` adds an arg group (using `.args(…)`).
+/// - `if condition => [a, b, …]` (or `if condition => a`) adds args only if the
+///   condition is true.
+/// - `..iterable` adds each arg in the iterable as a separate arg (using
+///   `.arg_each(…)`).
+/// - Any other expression adds a single arg (using `.arg(…)`).
+///
+/// Values can be anything that implements `AsRef<OsStr>`, and the elements of
+/// a group do not need to have the same type.
+#[macro_export]
+macro_rules! cmd {
+    ($program:expr $(, $($rest:tt)*)?) => {{
+        #[allow(unused_mut)]
+        let mut printable_shell_command = $crate::PrintableShellCommand::new($program);
+        $($crate::__cmd_args!(printable_shell_command; $($rest)*);)?
+        printable_shell_command
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cmd_args {
+    ($command:ident;) => {};
+    ($command:ident; if $condition:expr => [$($arg:expr),* $(,)?] $(, $($rest:tt)*)?) => {
+        if $condition {
+            $crate::__cmd_args!($command; [$($arg),*]);
+        }
+        $($crate::__cmd_args!($command; $($rest)*);)?
+    };
+    ($command:ident; if $condition:expr => $arg:expr $(, $($rest:tt)*)?) => {
+        if $condition {
+            $command.arg($arg);
+        }
+        $($crate::__cmd_args!($command; $($rest)*);)?
+    };
+    ($command:ident; .. $args:expr $(, $($rest:tt)*)?) => {
+        $command.arg_each($args);
+        $($crate::__cmd_args!($command; $($rest)*);)?
+    };
+    ($command:ident; [$($arg:expr),* $(,)?] $(, $($rest:tt)*)?) => {
+        $command.args::<&[&::std::ffi::OsStr], _>(&[$(
+            ::std::convert::AsRef::<::std::ffi::OsStr>::as_ref(&$arg)
+        ),*]);
+        $($crate::__cmd_args!($command; $($rest)*);)?
+    };
+    ($command:ident; $arg:expr $(, $($rest:tt)*)?) => {
+        $command.arg($arg);
+        $($crate::__cmd_args!($command; $($rest)*);)?
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, str::Utf8Error};
+
+    use crate::{cmd, PrintableShellCommand, ShellPrintable};
+
+    #[test]
+    fn cmd() -> Result<(), Utf8Error> {
+        let exclusions = [".DS_Store".to_owned(), ".git".to_owned()];
+        let source = PathBuf::from("./dist/web/");
+        let dry_run = false;
+        let verbose = true;
+        let command = cmd!(
+            "rsync",
+            "-avz",
+            if dry_run => ["--dry-run"],
+            if verbose => "--progress",
+            ["--exclude", &exclusions[0]],
+            ["--exclude", exclusions[1].clone()],
+            ..["--delete", "--checksum"],
+            source,
+            "example.com:~/web/"
+        );
+        let mut expected = PrintableShellCommand::new("rsync");
+        expected
+            .arg("-avz")
+            .arg("--progress")
+            .args(["--exclude", ".DS_Store"])
+            .args(["--exclude", ".git"])
+            .arg_each(["--delete", "--checksum"])
+            .arg("./dist/web/")
+            .arg("example.com:~/web/");
+        assert_eq!(
+            command.printable_invocation_string()?,
+            expected.printable_invocation_string()?
+        );
+        assert_eq!(
+            cmd!("ls").printable_invocation_string()?,
+            PrintableShellCommand::new("ls").printable_invocation_string()?
+        );
+        assert_eq!(cmd!("ls", [],).get_args().count(), 0);
+        assert_eq!(cmd!("ls", []).printable_invocation_string()?, "ls");
+        assert_eq!(
+            cmd!("ls", ["-l"], [], "src").printable_invocation_string()?,
+            "ls \\\n  -l \\\n  src"
+        );
+        Ok(())
+    }
+}
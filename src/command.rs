@@ -1,10 +1,41 @@
 use std::{ffi::OsStr, process::Command, str::Utf8Error};
 
 use crate::{
-    print_builder::PrintBuilder, shell_printable::ShellPrintableWithOptions, FormattingOptions,
-    ShellPrintable,
+    arg_grouping::group_args, print_builder::PrintBuilder,
+    shell_printable::ShellPrintableWithOptions, FormattingOptions, ShellPrintable,
 };
 
+/// Copies the working directory and any env vars that were explicitly set or
+/// removed. (The rest of the configuration of a `Command` cannot be read.)
+pub(crate) fn copy_env_and_current_dir(from: &Command, to: &mut Command) {
+    if let Some(current_dir) = from.get_current_dir() {
+        to.current_dir(current_dir);
+    }
+    for (key, value) in from.get_envs() {
+        match value {
+            Some(value) => to.env(key, value),
+            None => to.env_remove(key),
+        };
+    }
+}
+
+/// The value of `key` if it was set (`Some(Some(…))`) or removed
+/// (`Some(None)`) for `command`.
+pub(crate) fn env_override<'a>(command: &'a Command, key: &str) -> Option<Option<&'a OsStr>> {
+    command
+        .get_envs()
+        .find(|(env_key, _)| *env_key == key)
+        .map(|(_, value)| value)
+}
+
+/// Sets up `print_builder` for the working directory and `HOME` of `command`.
+pub(crate) fn set_print_builder_environment(print_builder: &mut PrintBuilder, command: &Command) {
+    print_builder.set_working_directory(command.get_current_dir());
+    if let Some(home) = env_override(command, "HOME") {
+        print_builder.set_home_override(home);
+    }
+}
+
 pub(crate) fn add_arg_from_command_lossy(print_builder: &mut PrintBuilder, arg: &OsStr) {
     print_builder.add_single_arg(&arg.to_string_lossy());
 }
@@ -17,49 +48,93 @@ pub(crate) fn add_arg_from_command(
     Ok(())
 }
 
+fn arg_groups<'a>(
+    command: &'a Command,
+    formatting_options: &FormattingOptions,
+) -> Vec<Vec<&'a OsStr>> {
+    group_args(
+        command.get_program(),
+        &command.get_args().collect::<Vec<_>>(),
+        formatting_options.arg_grouping.unwrap_or_default(),
+    )
+}
+
+fn print_builder_lossy(command: &Command, formatting_options: FormattingOptions) -> PrintBuilder {
+    let arg_groups = arg_groups(command, &formatting_options);
+    let mut print_builder =
+        PrintBuilder::new(&command.get_program().to_string_lossy(), formatting_options);
+    set_print_builder_environment(&mut print_builder, command);
+    for arg_group in arg_groups {
+        print_builder.add_arg_group(arg_group.iter().map(|arg| arg.to_string_lossy()));
+    }
+    print_builder
+}
+
+fn print_builder(
+    command: &Command,
+    formatting_options: FormattingOptions,
+) -> Result<PrintBuilder, Utf8Error> {
+    let arg_groups = arg_groups(command, &formatting_options);
+    let mut print_builder = PrintBuilder::new(
+        TryInto::<&str>::try_into(command.get_program())?,
+        formatting_options,
+    );
+    set_print_builder_environment(&mut print_builder, command);
+    for arg_group in arg_groups {
+        let strings = arg_group
+            .into_iter()
+            .map(TryInto::<&str>::try_into)
+            .collect::<Result<Vec<_>, _>>()?;
+        print_builder.add_arg_group(strings.into_iter());
+    }
+    Ok(print_builder)
+}
+
 impl ShellPrintableWithOptions for Command {
     fn printable_invocation_string_lossy_with_options(
         &self,
         formatting_options: FormattingOptions,
     ) -> String {
-        let mut print_builder =
-            PrintBuilder::new(&self.get_program().to_string_lossy(), formatting_options);
-        for arg in self.get_args() {
-            add_arg_from_command_lossy(&mut print_builder, arg);
-        }
-        print_builder.get()
+        print_builder_lossy(self, formatting_options).get()
     }
 
     fn printable_invocation_string_with_options(
         &self,
         formatting_options: FormattingOptions,
     ) -> Result<String, Utf8Error> {
-        let mut print_builder = PrintBuilder::new(
-            TryInto::<&str>::try_into(self.get_program())?,
-            formatting_options,
-        );
-        for arg in self.get_args() {
-            add_arg_from_command(&mut print_builder, arg)?;
-        }
-        Ok(print_builder.get())
+        Ok(print_builder(self, formatting_options)?.get())
+    }
+
+    fn printable_invocation_html_lossy_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> String {
+        print_builder_lossy(self, formatting_options).get_html()
+    }
+
+    fn printable_invocation_html_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> Result<String, Utf8Error> {
+        Ok(print_builder(self, formatting_options)?.get_html())
     }
 }
 
 impl ShellPrintable for Command {
     fn printable_invocation_string(&self) -> Result<String, Utf8Error> {
-        self.printable_invocation_string_with_options(Default::default())
+        self.printable_invocation_string_with_options(FormattingOptions::process_default())
     }
 
     fn printable_invocation_string_lossy(&self) -> String {
-        self.printable_invocation_string_lossy_with_options(Default::default())
+        self.printable_invocation_string_lossy_with_options(FormattingOptions::process_default())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::process::Command;
+    use std::{process::Command, str::Utf8Error};
 
-    use crate::ShellPrintable;
+    use crate::{ArgGrouping, FormattingOptions, ShellPrintable, ShellPrintableWithOptions};
 
     #[test]
     fn echo() -> Result<(), String> {
@@ -75,4 +150,25 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn heuristic_arg_grouping() -> Result<(), Utf8Error> {
+        let mut command = Command::new("rsync");
+        command
+            .args(["-avz", "--exclude", ".DS_Store", "--exclude", ".git"])
+            .args(["--", "./dist/", "example.com:~/dist/"]);
+        assert_eq!(
+            command.printable_invocation_string_with_options(
+                FormattingOptions::default().arg_grouping(ArgGrouping::Heuristic)
+            )?,
+            "rsync \\
+  -avz \\
+  --exclude .DS_Store \\
+  --exclude .git \\
+  -- \\
+  ./dist/ \\
+  example.com:~/dist/"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,633 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    format::os_str_to_str,
+    print_builder::PrintBuilder,
+    sequence_diff::{diff_sequences, DiffOp},
+    ArgumentLineWrapping, FormattingOptions, PrintableShellCommand,
+};
+
+const REMOVED_COLOR: &str = "\x1b[31m";
+const ADDED_COLOR: &str = "\x1b[32m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+/// How one part of a command differs between the old and new command.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DiffEntry<T> {
+    Unchanged(T),
+    /// Only present in the new command.
+    Added(T),
+    /// Only present in the old command.
+    Removed(T),
+    /// Present in both commands, with a different value.
+    Modified {
+        old: T,
+        new: T,
+    },
+}
+
+impl<T> DiffEntry<T> {
+    pub fn is_unchanged(&self) -> bool {
+        matches!(self, DiffEntry::Unchanged(_))
+    }
+
+    /// The value in the old command, if it has one.
+    pub fn old_value(&self) -> Option<&T> {
+        match self {
+            DiffEntry::Unchanged(old)
+            | DiffEntry::Removed(old)
+            | DiffEntry::Modified { old, .. } => Some(old),
+            DiffEntry::Added(_) => None,
+        }
+    }
+
+    /// The value in the new command, if it has one.
+    pub fn new_value(&self) -> Option<&T> {
+        match self {
+            DiffEntry::Unchanged(new) | DiffEntry::Added(new) | DiffEntry::Modified { new, .. } => {
+                Some(new)
+            }
+            DiffEntry::Removed(_) => None,
+        }
+    }
+
+    fn from_options(old: Option<T>, new: Option<T>) -> Option<Self>
+    where
+        T: PartialEq,
+    {
+        match (old, new) {
+            (None, None) => None,
+            (Some(old), None) => Some(DiffEntry::Removed(old)),
+            (None, Some(new)) => Some(DiffEntry::Added(new)),
+            (Some(old), Some(new)) if old == new => Some(DiffEntry::Unchanged(new)),
+            (Some(old), Some(new)) => Some(DiffEntry::Modified { old, new }),
+        }
+    }
+}
+
+/// The differences between two commands (see
+/// [`PrintableShellCommand::diff`]).
+///
+/// Arg groups are compared by their args (comments are ignored). A removed arg
+/// group and an added arg group at the same position that start with the same
+/// arg (e.g. `--exclude .git` and `--exclude .svn`) are reported as a single
+/// modified arg group.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommandDiff {
+    pub program: DiffEntry<OsString>,
+    pub arg_groups: Vec<DiffEntry<Vec<OsString>>>,
+    /// Env vars that were set or removed (`None`) for either command, sorted
+    /// by name.
+    pub env_vars: Vec<(OsString, DiffEntry<Option<OsString>>)>,
+    /// `None` if neither command sets a working directory.
+    pub current_dir: Option<DiffEntry<PathBuf>>,
+    /// Whether each command clears its environment (see
+    /// [`PrintableShellCommand::env_clear`]).
+    pub env_cleared: DiffEntry<bool>,
+}
+
+/// Adds the removed and added arg groups of one position where the sequences
+/// differ, pairing up removed and added groups that start with the same arg.
+fn push_changed_arg_groups(
+    arg_groups: &mut Vec<DiffEntry<Vec<OsString>>>,
+    removed: &mut Vec<&Vec<OsString>>,
+    added: &mut Vec<&Vec<OsString>>,
+) {
+    let mut removed = removed.drain(..);
+    let mut added = added.drain(..);
+    loop {
+        match (removed.next(), added.next()) {
+            (None, None) => break,
+            (Some(old), Some(new)) if old.first() == new.first() => {
+                arg_groups.push(DiffEntry::Modified {
+                    old: old.clone(),
+                    new: new.clone(),
+                })
+            }
+            (old, new) => {
+                arg_groups.extend(old.cloned().map(DiffEntry::Removed));
+                arg_groups.extend(new.cloned().map(DiffEntry::Added));
+            }
+        }
+    }
+}
+
+fn diff_arg_groups(old: &[Vec<OsString>], new: &[Vec<OsString>]) -> Vec<DiffEntry<Vec<OsString>>> {
+    let mut arg_groups = vec![];
+    let mut removed: Vec<&Vec<OsString>> = vec![];
+    let mut added: Vec<&Vec<OsString>> = vec![];
+    for op in diff_sequences(old, new) {
+        match op {
+            DiffOp::Unchanged(arg_group) => {
+                push_changed_arg_groups(&mut arg_groups, &mut removed, &mut added);
+                arg_groups.push(DiffEntry::Unchanged(arg_group.clone()));
+            }
+            DiffOp::Removed(arg_group) => removed.push(arg_group),
+            DiffOp::Added(arg_group) => added.push(arg_group),
+        }
+    }
+    push_changed_arg_groups(&mut arg_groups, &mut removed, &mut added);
+    arg_groups
+}
+
+fn env_vars(command: &PrintableShellCommand) -> BTreeMap<OsString, Option<OsString>> {
+    command
+        .get_envs()
+        .map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned)))
+        .collect()
+}
+
+fn to_str(s: &OsStr) -> io::Result<Cow<'_, str>> {
+    Ok(Cow::Borrowed(os_str_to_str(s)?))
+}
+
+fn to_str_lossy(s: &OsStr) -> io::Result<Cow<'_, str>> {
+    Ok(s.to_string_lossy())
+}
+
+impl CommandDiff {
+    pub fn new(old: &PrintableShellCommand, new: &PrintableShellCommand) -> Self {
+        let program = if old.get_program() == new.get_program() {
+            DiffEntry::Unchanged(new.get_program().to_owned())
+        } else {
+            DiffEntry::Modified {
+                old: old.get_program().to_owned(),
+                new: new.get_program().to_owned(),
+            }
+        };
+
+        let mut old_env_vars = env_vars(old);
+        let mut new_env_vars = env_vars(new);
+        let mut keys: Vec<OsString> = old_env_vars
+            .keys()
+            .chain(new_env_vars.keys())
+            .cloned()
+            .collect();
+        keys.sort();
+        keys.dedup();
+        let env_vars = keys
+            .into_iter()
+            .filter_map(|key| {
+                let entry =
+                    DiffEntry::from_options(old_env_vars.remove(&key), new_env_vars.remove(&key))?;
+                Some((key, entry))
+            })
+            .collect();
+
+        Self {
+            program,
+            arg_groups: diff_arg_groups(&old.arg_group_args(), &new.arg_group_args()),
+            env_vars,
+            current_dir: DiffEntry::from_options(
+                old.get_current_dir().map(Path::to_owned),
+                new.get_current_dir().map(Path::to_owned),
+            ),
+            env_cleared: DiffEntry::from_options(
+                Some(old.is_env_cleared()),
+                Some(new.is_env_cleared()),
+            )
+            .expect("Both values are set."),
+        }
+    }
+
+    /// Whether the commands differ in anything but comments.
+    pub fn has_changes(&self) -> bool {
+        !(self.program.is_unchanged()
+            && self.arg_groups.iter().all(DiffEntry::is_unchanged)
+            && self.env_vars.iter().all(|(_, entry)| entry.is_unchanged())
+            && self.env_cleared.is_unchanged()
+            && self
+                .current_dir
+                .as_ref()
+                .is_none_or(DiffEntry::is_unchanged))
+    }
+
+    /// Renders the diff like a unified diff of the two commands printed with
+    /// [`ArgumentLineWrapping::ByEntry`]: one line per arg group, each starting
+    /// with ` ` (unchanged), `-` (removed), or `+` (added). A modified arg group
+    /// is shown as a removed line followed by an added line. The working
+    /// directory and env vars are shown before the command as statements in
+    /// the dialect of `formatting_options` (e.g. `cd …`, `export …=…`, and
+    /// `unset …` for POSIX shells, or `$env:… = …` for PowerShell). A command
+    /// that clears its environment is shown with a `# (environment cleared)`
+    /// comment line.
+    ///
+    /// If `formatting_options.color` is set, removed lines are red and added
+    /// lines are green (using ANSI escape codes). Tokens within a line are not
+    /// styled, since that would reset the color of the line.
+    ///
+    /// This returns an error if an env var name is not a valid shell variable
+    /// name, or if the commands are not valid UTF-8.
+    pub fn printable_diff_string(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> io::Result<String> {
+        self.render(formatting_options, to_str)
+    }
+
+    /// Like `printable_diff_string(…)`, but converts non-UTF-8 values using
+    /// `.to_string_lossy()`. This still returns an error if an env var name is
+    /// not a valid shell variable name.
+    pub fn printable_diff_string_lossy(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> io::Result<String> {
+        self.render(formatting_options, to_str_lossy)
+    }
+
+    /// A print builder for the old or new side of the diff.
+    fn print_builder<'a>(
+        &'a self,
+        side: fn(&'a DiffEntry<Vec<OsString>>) -> Option<&'a Vec<OsString>>,
+        program: &OsStr,
+        current_dir: Option<&Path>,
+        home: Option<&Option<OsString>>,
+        formatting_options: &FormattingOptions,
+        to_str: fn(&OsStr) -> io::Result<Cow<'_, str>>,
+    ) -> io::Result<PrintBuilder> {
+        let mut print_builder = PrintBuilder::new(&to_str(program)?, formatting_options.clone());
+        print_builder.set_working_directory(current_dir);
+        if let Some(home) = home {
+            print_builder.set_home_override(home.as_deref());
+        }
+        for arg_group in self.arg_groups.iter().filter_map(side) {
+            let strings = arg_group
+                .iter()
+                .map(|arg| to_str(arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            print_builder.add_arg_group(strings.iter());
+        }
+        Ok(print_builder)
+    }
+
+    fn render(
+        &self,
+        formatting_options: FormattingOptions,
+        to_str: fn(&OsStr) -> io::Result<Cow<'_, str>>,
+    ) -> io::Result<String> {
+        let colored = formatting_options.color.unwrap_or(false);
+        let formatting_options = formatting_options
+            .color(false)
+            .argument_line_wrapping(ArgumentLineWrapping::ByEntry)
+            .skip_line_wrap_before_first_arg(false);
+        let old_current_dir = self.current_dir.as_ref().and_then(DiffEntry::old_value);
+        let new_current_dir = self.current_dir.as_ref().and_then(DiffEntry::new_value);
+        let home = self
+            .env_vars
+            .iter()
+            .find(|(key, _)| key == "HOME")
+            .map(|(_, entry)| entry);
+        let old = self.print_builder(
+            DiffEntry::old_value,
+            self.program
+                .old_value()
+                .expect("The program is never added."),
+            old_current_dir.map(PathBuf::as_path),
+            home.and_then(DiffEntry::old_value),
+            &formatting_options,
+            to_str,
+        )?;
+        let new = self.print_builder(
+            DiffEntry::new_value,
+            self.program
+                .new_value()
+                .expect("The program is never removed."),
+            new_current_dir.map(PathBuf::as_path),
+            home.and_then(DiffEntry::new_value),
+            &formatting_options,
+            to_str,
+        )?;
+
+        let mut statement_lines: Vec<(char, String)> = vec![];
+        let mut push_statements = |entry: &DiffEntry<String>| {
+            match entry {
+                DiffEntry::Unchanged(statement) => statement_lines.push((' ', statement.clone())),
+                DiffEntry::Added(statement) => statement_lines.push(('+', statement.clone())),
+                DiffEntry::Removed(statement) => statement_lines.push(('-', statement.clone())),
+                DiffEntry::Modified { old, new } => {
+                    statement_lines.push(('-', old.clone()));
+                    statement_lines.push(('+', new.clone()));
+                }
+            };
+        };
+        let cd_statement = |current_dir: &PathBuf| -> io::Result<String> {
+            Ok(new.cd_statement(&to_str(current_dir.as_os_str())?))
+        };
+        if let Some(current_dir) = &self.current_dir {
+            push_statements(&map_entry(current_dir, cd_statement)?);
+        }
+        let env_clear_comment = |env_cleared: &bool| env_cleared.then(|| new.env_clear_comment());
+        if let Some(entry) = DiffEntry::from_options(
+            self.env_cleared.old_value().and_then(env_clear_comment),
+            self.env_cleared.new_value().and_then(env_clear_comment),
+        ) {
+            push_statements(&entry);
+        }
+        for (key, value) in &self.env_vars {
+            let key = to_str(key)?;
+            let env_statement = |value: &Option<OsString>| -> io::Result<String> {
+                match value {
+                    Some(value) => new.env_statement(&key, Some(&to_str(value)?)),
+                    None => new.env_statement(&key, None),
+                }
+            };
+            push_statements(&map_entry(value, env_statement)?);
+        }
+
+        let old_lines = old.get_lines();
+        let new_lines = new.get_lines();
+        let mut command_lines: Vec<(char, &str)> = vec![];
+        if self.program.is_unchanged() {
+            command_lines.push((' ', &new_lines[0]));
+        } else {
+            command_lines.push(('-', &old_lines[0]));
+            command_lines.push(('+', &new_lines[0]));
+        }
+        let (mut old_index, mut new_index) = (1, 1);
+        for arg_group in &self.arg_groups {
+            if arg_group.is_unchanged() {
+                command_lines.push((' ', &new_lines[new_index]));
+            } else {
+                if arg_group.old_value().is_some() {
+                    command_lines.push(('-', &old_lines[old_index]));
+                }
+                if arg_group.new_value().is_some() {
+                    command_lines.push(('+', &new_lines[new_index]));
+                }
+            }
+            old_index += arg_group.old_value().is_some() as usize;
+            new_index += arg_group.new_value().is_some() as usize;
+        }
+
+        let line_continuation = new.line_continuation();
+        let last_command_line_index = command_lines.len() - 1;
+        let lines = statement_lines
+            .into_iter()
+            .chain(
+                command_lines
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (marker, line))| {
+                        if index == last_command_line_index {
+                            (marker, line.to_owned())
+                        } else {
+                            (marker, format!("{}{}", line, line_continuation))
+                        }
+                    }),
+            );
+        Ok(lines
+            .map(|(marker, line)| match (colored, marker) {
+                (true, '-') => format!("{}{}{}{}", REMOVED_COLOR, marker, line, RESET_COLOR),
+                (true, '+') => format!("{}{}{}{}", ADDED_COLOR, marker, line, RESET_COLOR),
+                _ => format!("{}{}", marker, line),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+}
+
+fn map_entry<T, U>(
+    entry: &DiffEntry<T>,
+    f: impl Fn(&T) -> io::Result<U>,
+) -> io::Result<DiffEntry<U>> {
+    Ok(match entry {
+        DiffEntry::Unchanged(value) => DiffEntry::Unchanged(f(value)?),
+        DiffEntry::Added(value) => DiffEntry::Added(f(value)?),
+        DiffEntry::Removed(value) => DiffEntry::Removed(f(value)?),
+        DiffEntry::Modified { old, new } => DiffEntry::Modified {
+            old: f(old)?,
+            new: f(new)?,
+        },
+    })
+}
+
+impl PrintableShellCommand {
+    /// The differences between this command and `new` (see [`CommandDiff`]).
+    pub fn diff(&self, new: &PrintableShellCommand) -> CommandDiff {
+        CommandDiff::new(self, new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, io, path::PathBuf};
+
+    use crate::{DiffEntry, FormattingOptions, PrintableShellCommand, ShellDialect};
+
+    fn os_strings(strings: &[&str]) -> Vec<OsString> {
+        strings.iter().map(OsString::from).collect()
+    }
+
+    fn rsync_commands_for_testing() -> (PrintableShellCommand, PrintableShellCommand) {
+        let mut old = PrintableShellCommand::new("rsync");
+        old.arg("-avz")
+            .args(["--exclude", ".git"])
+            .args(["--exclude", ".DS_Store"])
+            .arg("--delete")
+            .args(["./dist/", "example.com:~/dist/"]);
+        old.current_dir("/home/me/project").env("RSYNC_RSH", "ssh");
+        let mut new = PrintableShellCommand::new("rsync");
+        new.arg("-avz")
+            .args(["--exclude", ".svn"])
+            .args(["--exclude", ".DS_Store"])
+            .arg("--dry-run")
+            .args(["./dist/", "example.com:~/dist/"]);
+        new.current_dir("/home/me/project")
+            .env("RSYNC_RSH", "ssh -p 2222")
+            .env_remove("RSYNC_PASSWORD");
+        (old, new)
+    }
+
+    #[test]
+    fn diff() {
+        let (old, new) = rsync_commands_for_testing();
+        let diff = old.diff(&new);
+        assert!(diff.has_changes());
+        assert_eq!(diff.program, DiffEntry::Unchanged("rsync".into()));
+        assert_eq!(
+            diff.arg_groups,
+            [
+                DiffEntry::Unchanged(os_strings(&["-avz"])),
+                DiffEntry::Modified {
+                    old: os_strings(&["--exclude", ".git"]),
+                    new: os_strings(&["--exclude", ".svn"])
+                },
+                DiffEntry::Unchanged(os_strings(&["--exclude", ".DS_Store"])),
+                DiffEntry::Removed(os_strings(&["--delete"])),
+                DiffEntry::Added(os_strings(&["--dry-run"])),
+                DiffEntry::Unchanged(os_strings(&["./dist/", "example.com:~/dist/"])),
+            ]
+        );
+        assert_eq!(
+            diff.env_vars,
+            [
+                ("RSYNC_PASSWORD".into(), DiffEntry::Added(None)),
+                (
+                    "RSYNC_RSH".into(),
+                    DiffEntry::Modified {
+                        old: Some("ssh".into()),
+                        new: Some("ssh -p 2222".into())
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            diff.current_dir,
+            Some(DiffEntry::Unchanged(PathBuf::from("/home/me/project")))
+        );
+
+        // Arg groups are compared as a whole, so regrouping args is a change.
+        let mut regrouped = PrintableShellCommand::new("echo");
+        regrouped.arg("hello").arg("world");
+        let mut grouped = PrintableShellCommand::new("echo");
+        grouped.args(["hello", "world"]);
+        assert!(!grouped.diff(&grouped).has_changes());
+        assert!(grouped.diff(&regrouped).has_changes());
+    }
+
+    #[test]
+    fn env_clear() -> io::Result<()> {
+        let mut old = PrintableShellCommand::new("make");
+        old.env("CC", "clang");
+        let mut new = PrintableShellCommand::new("make");
+        new.env_clear().env("CC", "clang");
+        let diff = old.diff(&new);
+        assert!(diff.has_changes());
+        assert_eq!(
+            diff.env_cleared,
+            DiffEntry::Modified {
+                old: false,
+                new: true
+            }
+        );
+        assert_eq!(
+            diff.printable_diff_string(FormattingOptions::default())?,
+            "+# (environment cleared)
+ export CC=clang
+ make"
+        );
+        assert!(!new.diff(&new).has_changes());
+        Ok(())
+    }
+
+    #[test]
+    fn printable_diff_string() -> io::Result<()> {
+        let (old, new) = rsync_commands_for_testing();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.printable_diff_string(FormattingOptions::default())?,
+            " cd /home/me/project
++unset RSYNC_PASSWORD
+-export RSYNC_RSH=ssh
++export RSYNC_RSH='ssh -p 2222'
+ rsync \\
+   -avz \\
+-  --exclude .git \\
++  --exclude .svn \\
+   --exclude .DS_Store \\
+-  --delete \\
++  --dry-run \\
+   ./dist/ example.com:~/dist/"
+        );
+
+        let mut renamed = PrintableShellCommand::new("openrsync");
+        renamed.arg("-avz");
+        assert_eq!(
+            PrintableShellCommand::new("rsync")
+                .diff(&renamed)
+                .printable_diff_string_lossy(FormattingOptions::compact().color(true))?,
+            "\x1b[31m-rsync \\\x1b[0m
+\x1b[32m+openrsync \\\x1b[0m
+\x1b[32m+  -avz\x1b[0m"
+        );
+
+        let (old, new) = rsync_commands_for_testing();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.printable_diff_string_lossy(
+                FormattingOptions::default().dialect(ShellDialect::Fish)
+            )?
+            .lines()
+            .take(4)
+            .collect::<Vec<_>>(),
+            [
+                " cd /home/me/project",
+                "+set -e RSYNC_PASSWORD",
+                "-set -x RSYNC_RSH ssh",
+                "+set -x RSYNC_RSH 'ssh -p 2222'"
+            ]
+        );
+        assert_eq!(
+            diff.printable_diff_string_lossy(
+                FormattingOptions::default().dialect(ShellDialect::PowerShell)
+            )?
+            .lines()
+            .take(4)
+            .collect::<Vec<_>>(),
+            [
+                " cd /home/me/project",
+                "+$env:RSYNC_PASSWORD = $null",
+                "-$env:RSYNC_RSH = 'ssh'",
+                "+$env:RSYNC_RSH = 'ssh -p 2222'"
+            ]
+        );
+        assert_eq!(
+            diff.printable_diff_string_lossy(
+                FormattingOptions::default().dialect(ShellDialect::Cmd)
+            )?
+            .lines()
+            .take(4)
+            .collect::<Vec<_>>(),
+            [
+                " cd /d /home/me/project",
+                "+set RSYNC_PASSWORD=",
+                "-set RSYNC_RSH=ssh",
+                "+set RSYNC_RSH=ssh -p 2222"
+            ]
+        );
+
+        let mut old = PrintableShellCommand::new("ls");
+        old.args::<[&str; 0], _>([]);
+        let mut new = PrintableShellCommand::new("ls");
+        new.arg("-l");
+        assert_eq!(
+            old.diff(&new)
+                .printable_diff_string(FormattingOptions::default())?,
+            " ls \\
++  -l"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_and_invalid_env_var_names() -> io::Result<()> {
+        let mut old = PrintableShellCommand::new("ls");
+        old.current_dir("-v");
+        let diff = old.diff(&old);
+        assert_eq!(
+            diff.printable_diff_string(FormattingOptions::default())?,
+            " cd -- -v\n ls"
+        );
+        assert_eq!(
+            diff.printable_diff_string(
+                FormattingOptions::default().dialect(ShellDialect::PowerShell)
+            )?,
+            " cd '-v'\n ls"
+        );
+
+        let mut new = PrintableShellCommand::new("ls");
+        new.env("X=1; reboot; Y", "1");
+        let error = PrintableShellCommand::new("ls")
+            .diff(&new)
+            .printable_diff_string_lossy(FormattingOptions::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+}
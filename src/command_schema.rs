@@ -0,0 +1,320 @@
+use std::{error::Error, ffi::OsStr, fmt::Display, path::Path, sync::RwLock};
+
+static REGISTERED_SCHEMAS: RwLock<Vec<(String, CommandSchema)>> = RwLock::new(Vec::new());
+
+/// Describes the flags of a program, so that its args can be grouped (see
+/// [`crate::ArgGrouping::Schema`]) and checked for mistakes.
+///
+/// Args that do not start with `-` (and all args after `--`) are positional.
+/// Flags written as `--flag=value` never take the next arg. A cluster of short
+/// flags (like `-xzf`) is recognized if all of its flags are boolean flags,
+/// except that the last one may take a value.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CommandSchema {
+    /// Flags that take the next arg as their value.
+    pub value_flags: Vec<String>,
+    /// Flags that never take a value.
+    pub boolean_flags: Vec<String>,
+}
+
+impl CommandSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn value_flags<I: IntoIterator<Item = S>, S: Into<String>>(mut self, flags: I) -> Self {
+        self.value_flags.extend(flags.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn boolean_flags<I: IntoIterator<Item = S>, S: Into<String>>(mut self, flags: I) -> Self {
+        self.boolean_flags.extend(flags.into_iter().map(Into::into));
+        self
+    }
+
+    /// Registers a schema for `program_name` for the current process. This
+    /// takes precedence over built-in schemas and earlier registrations.
+    pub fn register<S: Into<String>>(program_name: S, schema: CommandSchema) {
+        REGISTERED_SCHEMAS
+            .write()
+            .unwrap()
+            .insert(0, (program_name.into(), schema));
+    }
+
+    /// The schema for `program`, which may be a path (e.g. `/usr/bin/git`).
+    /// Registered schemas take precedence over built-in ones.
+    pub fn for_program(program: &OsStr) -> Option<CommandSchema> {
+        let program_name = program_name(program)?;
+        REGISTERED_SCHEMAS
+            .read()
+            .unwrap()
+            .iter()
+            .find(|(name, _)| name == program_name)
+            .map(|(_, schema)| schema.clone())
+            .or_else(|| Self::builtin(program_name))
+    }
+
+    /// The built-in schema for `program_name`, if there is one. Built-in
+    /// schemas cover common flags of `git`, `cargo`, `ffmpeg`, `rsync`,
+    /// `docker`, `curl`, and `tar`. Flags are merged across subcommands, so
+    /// flags that take a value for some subcommands but not others are left
+    /// out (e.g. `docker -t` is `--tty` for `docker run` but `--tag` for
+    /// `docker build`).
+    pub fn builtin(program_name: &str) -> Option<CommandSchema> {
+        let (value_flags, boolean_flags): (&str, &str) = match program_name {
+            "git" => (
+                "--git-dir --work-tree --message --file --author --date --format \
+                 --since --until --grep --depth --onto --strategy",
+                "--version --help -a --all --amend -v --verbose -q --quiet \
+                 --force --no-verify --oneline -p --patch --stat --rebase --ff-only \
+                 --no-ff --tags --prune --set-upstream -d -D --hard --soft \
+                 --cached --staged",
+            ),
+            "cargo" => (
+                "-p --package --bin --example --test --bench -F --features --target \
+                 --target-dir --manifest-path --profile -j --jobs --color \
+                 --message-format -Z --config --exclude",
+                "-r --release --all-features --no-default-features --workspace \
+                 --all-targets --lib --bins --examples --tests --benches -v \
+                 --verbose -q --quiet --locked --offline --frozen --no-run \
+                 --version --help",
+            ),
+            "ffmpeg" => (
+                "-i -f -c -codec -c:v -c:a -vcodec -acodec -b:v -b:a -r -s -ss -t \
+                 -to -filter:v -filter:a -vf -af -filter_complex -map -preset -crf \
+                 -pix_fmt -ar -ac -loglevel -v -threads -metadata -movflags",
+                "-y -n -an -vn -sn -hide_banner -nostdin -shortest",
+            ),
+            "rsync" => (
+                "-e --rsh --exclude --include --exclude-from --include-from \
+                 --filter --files-from --chmod --chown --bwlimit --log-file \
+                 --password-file --port --timeout -T --temp-dir --partial-dir \
+                 --backup-dir --suffix --rsync-path --max-size --min-size \
+                 --compare-dest --link-dest --copy-dest",
+                "-a --archive -v --verbose -z --compress -r --recursive -n \
+                 --dry-run --delete --progress -P -h --human-readable -u --update \
+                 -l --links -p --perms -t --times -c --checksum -q --quiet -H \
+                 --hard-links -x --one-file-system",
+            ),
+            "docker" => (
+                "-e --env --env-file --volume -w --workdir --publish --name \
+                 --network --entrypoint -u --user --mount --file --tag \
+                 --build-arg --platform --label --target --memory --cpus \
+                 --restart --hostname --add-host --format --filter",
+                "-d --detach --interactive --tty --rm --privileged --init -q \
+                 --quiet --no-cache --all --help",
+            ),
+            "curl" => (
+                "-o --output -X --request -H --header -d --data --data-raw \
+                 --data-binary --data-urlencode -F --form -u --user -A --user-agent \
+                 -e --referer -b --cookie -c --cookie-jar -x --proxy -w --write-out \
+                 --connect-timeout -m --max-time --retry -T --upload-file --cacert \
+                 --cert --key -r --range -K --config",
+                "-s --silent -S --show-error -L --location -f --fail -v --verbose \
+                 -k --insecure -I --head -i --include -O --remote-name --compressed \
+                 -G --get -N --no-buffer",
+            ),
+            "tar" => (
+                "-f --file -C --directory -T --files-from -X --exclude-from \
+                 --exclude -b --blocking-factor --transform --owner --group --mode \
+                 -I --use-compress-program",
+                "-c --create -x --extract -t --list -v --verbose -z --gzip -j \
+                 --bzip2 -J --xz --zstd -p --preserve-permissions -k \
+                 --keep-old-files -a --auto-compress --totals",
+            ),
+            _ => return None,
+        };
+        Some(
+            CommandSchema::new()
+                .value_flags(value_flags.split_whitespace())
+                .boolean_flags(boolean_flags.split_whitespace()),
+        )
+    }
+
+    /// Whether `arg` is a flag that takes the next arg as its value (either a
+    /// single value flag, or a cluster of short flags ending in one). `None` if
+    /// `arg` is not a known flag.
+    pub(crate) fn takes_value(&self, arg: &str) -> Option<bool> {
+        if self.value_flags.iter().any(|flag| flag == arg) {
+            return Some(true);
+        }
+        if self.boolean_flags.iter().any(|flag| flag == arg) {
+            return Some(false);
+        }
+        let cluster = arg.strip_prefix('-')?;
+        if cluster.starts_with('-') || cluster.chars().count() < 2 {
+            return None;
+        }
+        let short_flag = |c: char| format!("-{}", c);
+        let mut chars = cluster.chars().rev();
+        let last = short_flag(chars.next()?);
+        if !chars.all(|c| self.boolean_flags.contains(&short_flag(c))) {
+            return None;
+        }
+        if self.value_flags.contains(&last) {
+            Some(true)
+        } else if self.boolean_flags.contains(&last) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `arg` is a flag that is known to this schema.
+    fn is_known_flag(&self, arg: &str) -> bool {
+        self.takes_value(arg).is_some()
+    }
+
+    /// Checks `args` (not including the program) for value flags that are
+    /// missing their value: a value flag at the end of the args, or followed
+    /// by `--` or by another known flag.
+    pub fn validate<S: AsRef<OsStr>>(&self, args: &[S]) -> Vec<SchemaWarning> {
+        let mut warnings = vec![];
+        for (index, arg) in args.iter().enumerate() {
+            let Some(arg) = arg.as_ref().to_str() else {
+                continue;
+            };
+            if arg == "--" {
+                break;
+            }
+            if self.takes_value(arg) != Some(true) {
+                continue;
+            }
+            let next = args.get(index + 1).map(|next| next.as_ref().to_str());
+            let is_missing_value = match next {
+                None => true,
+                Some(None) => false,
+                Some(Some(next)) => next == "--" || self.is_known_flag(next),
+            };
+            if is_missing_value {
+                warnings.push(SchemaWarning::MissingValue {
+                    arg_index: index,
+                    flag: arg.to_owned(),
+                });
+            }
+        }
+        warnings
+    }
+}
+
+/// The program name used to look up schemas: the file name of the program,
+/// without an `.exe` extension.
+fn program_name(program: &OsStr) -> Option<&str> {
+    let file_name = Path::new(program).file_name()?.to_str()?;
+    Some(file_name.strip_suffix(".exe").unwrap_or(file_name))
+}
+
+/// A likely mistake in the args of a command, found using its
+/// [`CommandSchema`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SchemaWarning {
+    /// A flag that takes a value is not followed by one.
+    MissingValue {
+        /// The index of the flag (not counting the program).
+        arg_index: usize,
+        flag: String,
+    },
+}
+
+impl Display for SchemaWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaWarning::MissingValue { arg_index, flag } => {
+                write!(
+                    f,
+                    "Flag `{}` (arg {}) is missing its value",
+                    flag, arg_index
+                )
+            }
+        }
+    }
+}
+
+impl Error for SchemaWarning {}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::{CommandSchema, SchemaWarning};
+
+    #[test]
+    fn lookup() {
+        assert!(CommandSchema::for_program(OsStr::new("/usr/bin/git")).is_some());
+        assert!(CommandSchema::for_program(OsStr::new("curl.exe")).is_some());
+        assert!(CommandSchema::for_program(OsStr::new("schema-test-tool")).is_none());
+        CommandSchema::register(
+            "schema-test-tool",
+            CommandSchema::new().value_flags(["--level"]),
+        );
+        assert_eq!(
+            CommandSchema::for_program(OsStr::new("./schema-test-tool")),
+            Some(CommandSchema::new().value_flags(["--level"]))
+        );
+    }
+
+    #[test]
+    fn takes_value() {
+        let tar = CommandSchema::builtin("tar").unwrap();
+        assert_eq!(tar.takes_value("-f"), Some(true));
+        assert_eq!(tar.takes_value("-czf"), Some(true));
+        assert_eq!(tar.takes_value("-xv"), Some(false));
+        assert_eq!(tar.takes_value("-fz"), None);
+        assert_eq!(tar.takes_value("--unknown"), None);
+    }
+
+    #[test]
+    fn ambiguous_flags() {
+        let docker = CommandSchema::builtin("docker").unwrap();
+        for flag in [
+            "-t", "-f", "-a", "-l", "-v", "-p", "-i", "-h", "-m", "--pull",
+        ] {
+            assert_eq!(docker.takes_value(flag), None, "docker {}", flag);
+        }
+        assert_eq!(docker.validate(&["build", "-t", "tag", "."]), []);
+        assert_eq!(docker.validate(&["rm", "-f", "x"]), []);
+        assert_eq!(docker.validate(&["rm", "-f", "-v", "x"]), []);
+
+        let git = CommandSchema::builtin("git").unwrap();
+        for flag in [
+            "-u", "-m", "-b", "-B", "-c", "-C", "-f", "-F", "--branch", "--pretty",
+        ] {
+            assert_eq!(git.takes_value(flag), None, "git {}", flag);
+        }
+        assert_eq!(git.validate(&["branch", "-m", "-f", "new"]), []);
+    }
+
+    #[test]
+    fn validate() {
+        let curl = CommandSchema::builtin("curl").unwrap();
+        assert_eq!(
+            curl.validate(&[
+                "-H",
+                "--silent",
+                "-o",
+                "out.html",
+                "https://example.com",
+                "-X"
+            ]),
+            [
+                SchemaWarning::MissingValue {
+                    arg_index: 0,
+                    flag: "-H".to_owned()
+                },
+                SchemaWarning::MissingValue {
+                    arg_index: 5,
+                    flag: "-X".to_owned()
+                }
+            ]
+        );
+        assert_eq!(curl.validate(&["-d", "-", "--", "-o"]), []);
+        assert_eq!(
+            SchemaWarning::MissingValue {
+                arg_index: 0,
+                flag: "-H".to_owned()
+            }
+            .to_string(),
+            "Flag `-H` (arg 0) is missing its value"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use std::{env, error::Error, ffi::OsString, fmt::Display, sync::RwLock};
+
+use crate::{formatting_options::ParseOptionError, FormattingOptions};
+
+const ENV_VAR_PREFIX: &str = "PRINTABLE_SHELL_COMMAND_";
+
+static PROCESS_DEFAULT: RwLock<Option<FormattingOptions>> = RwLock::new(None);
+
+/// An environment variable was set to a value that could not be parsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidEnvVarError {
+    pub name: String,
+    pub value: OsString,
+    /// Why the value could not be parsed.
+    pub reason: String,
+}
+
+impl Display for InvalidEnvVarError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid value for environment variable `{}`: {:?} ({})",
+            self.name, self.value, self.reason
+        )
+    }
+}
+
+impl Error for InvalidEnvVarError {}
+
+fn parse_env_var<T>(
+    get_var: &impl Fn(&str) -> Option<OsString>,
+    suffix: &str,
+    parse: impl Fn(&str) -> Result<T, ParseOptionError>,
+) -> Result<Option<T>, InvalidEnvVarError> {
+    let name = format!("{}{}", ENV_VAR_PREFIX, suffix);
+    let Some(value) = get_var(&name) else {
+        return Ok(None);
+    };
+    if value.is_empty() {
+        return Ok(None);
+    }
+    let Some(value_str) = value.to_str() else {
+        return Err(InvalidEnvVarError {
+            name,
+            value,
+            reason: "not valid UTF-8".to_owned(),
+        });
+    };
+    match parse(value_str) {
+        Ok(parsed) => Ok(Some(parsed)),
+        Err(error) => Err(InvalidEnvVarError {
+            name,
+            value,
+            reason: format!("expected one of: {}", error.expected.join(", ")),
+        }),
+    }
+}
+
+fn parse_string(s: &str) -> Result<String, ParseOptionError> {
+    Ok(s.to_owned())
+}
+
+fn parse_bool(s: &str) -> Result<bool, ParseOptionError> {
+    match s {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(ParseOptionError::new(s, &["true", "false", "1", "0"])),
+    }
+}
+
+impl FormattingOptions {
+    /// Reads formatting options from environment variables. Unset (or empty)
+    /// variables leave the corresponding option unset.
+    ///
+    /// | Variable | Values |
+    /// | --- | --- |
+    /// | `PRINTABLE_SHELL_COMMAND_MAIN_INDENTATION` | any string |
+    /// | `PRINTABLE_SHELL_COMMAND_ARG_INDENTATION` | any string |
+    /// | `PRINTABLE_SHELL_COMMAND_QUOTING` | `auto`, `extra-safe` |
+    /// | `PRINTABLE_SHELL_COMMAND_ARGUMENT_LINE_WRAPPING` | `by-entry`, `nested-by-entry`, `by-argument`, `inline` |
+    /// | `PRINTABLE_SHELL_COMMAND_SKIP_LINE_WRAP_BEFORE_FIRST_ARG` | `true`, `false`, `1`, `0` |
+    /// | `PRINTABLE_SHELL_COMMAND_HYPERLINK_PATHS` | `true`, `false`, `1`, `0` |
+    /// | `PRINTABLE_SHELL_COMMAND_PATH_ABBREVIATION` | `none`, `home`, `home-and-working-directory` |
+    /// | `PRINTABLE_SHELL_COMMAND_DIALECT` | `posix`, `bash`, `zsh`, `fish`, `powershell`, `cmd` |
+    /// | `PRINTABLE_SHELL_COMMAND_SANITIZE_UNTRUSTED_CHARACTERS` | `true`, `false`, `1`, `0` |
+    /// | `PRINTABLE_SHELL_COMMAND_ARG_GROUPING` | `separate`, `heuristic`, `schema` |
+    /// | `PRINTABLE_SHELL_COMMAND_COLOR` | `true`, `false`, `1`, `0` |
+    pub fn from_env() -> Result<Self, InvalidEnvVarError> {
+        Self::from_env_vars(|name| env::var_os(name))
+    }
+
+    fn from_env_vars(
+        get_var: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, InvalidEnvVarError> {
+        Ok(Self {
+            main_indentation: parse_env_var(&get_var, "MAIN_INDENTATION", parse_string)?,
+            arg_indentation: parse_env_var(&get_var, "ARG_INDENTATION", parse_string)?,
+            quoting: parse_env_var(&get_var, "QUOTING", str::parse)?,
+            argument_line_wrapping: parse_env_var(&get_var, "ARGUMENT_LINE_WRAPPING", str::parse)?,
+            skip_line_wrap_before_first_arg: parse_env_var(
+                &get_var,
+                "SKIP_LINE_WRAP_BEFORE_FIRST_ARG",
+                parse_bool,
+            )?,
+            hyperlink_paths: parse_env_var(&get_var, "HYPERLINK_PATHS", parse_bool)?,
+            path_abbreviation: parse_env_var(&get_var, "PATH_ABBREVIATION", str::parse)?,
+            dialect: parse_env_var(&get_var, "DIALECT", str::parse)?,
+            sanitize_untrusted_characters: parse_env_var(
+                &get_var,
+                "SANITIZE_UNTRUSTED_CHARACTERS",
+                parse_bool,
+            )?,
+            arg_grouping: parse_env_var(&get_var, "ARG_GROUPING", str::parse)?,
+            color: parse_env_var(&get_var, "COLOR", parse_bool)?,
+        })
+    }
+
+    /// Sets the options used by [`crate::ShellPrintable`] (e.g.
+    /// `.print_invocation()`) for the rest of the process.
+    ///
+    /// To let end users of a CLI choose the output style, call this near the
+    /// start of `main()`:
+    ///
+    /// ```
+    /// use printable_shell_command::FormattingOptions;
+    ///
+    /// FormattingOptions::set_process_default(
+    ///     FormattingOptions::ci_log().merge(FormattingOptions::from_env().unwrap()),
+    /// );
+    /// ```
+    pub fn set_process_default(formatting_options: FormattingOptions) {
+        *PROCESS_DEFAULT.write().unwrap() = Some(formatting_options);
+    }
+
+    /// The options used by [`crate::ShellPrintable`]. These are the same as
+    /// `FormattingOptions::default()` unless
+    /// [`FormattingOptions::set_process_default`] has been called.
+    pub fn process_default() -> FormattingOptions {
+        PROCESS_DEFAULT.read().unwrap().clone().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, ffi::OsString};
+
+    use crate::{ArgumentLineWrapping, FormattingOptions, InvalidEnvVarError, Quoting};
+
+    fn from_map(vars: &[(&str, &str)]) -> Result<FormattingOptions, InvalidEnvVarError> {
+        let vars: HashMap<String, OsString> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.into()))
+            .collect();
+        FormattingOptions::from_env_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn from_env_vars() {
+        assert_eq!(from_map(&[]), Ok(FormattingOptions::default()));
+        assert_eq!(
+            from_map(&[
+                ("PRINTABLE_SHELL_COMMAND_QUOTING", "extra-safe"),
+                ("PRINTABLE_SHELL_COMMAND_ARGUMENT_LINE_WRAPPING", "inline"),
+                (
+                    "PRINTABLE_SHELL_COMMAND_SKIP_LINE_WRAP_BEFORE_FIRST_ARG",
+                    "1"
+                ),
+                ("PRINTABLE_SHELL_COMMAND_ARG_INDENTATION", "    "),
+                ("PRINTABLE_SHELL_COMMAND_MAIN_INDENTATION", ""),
+            ]),
+            Ok(FormattingOptions {
+                quoting: Some(Quoting::ExtraSafe),
+                argument_line_wrapping: Some(ArgumentLineWrapping::Inline),
+                skip_line_wrap_before_first_arg: Some(true),
+                arg_indentation: Some("    ".to_owned()),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn from_env_vars_invalid() {
+        assert_eq!(
+            from_map(&[("PRINTABLE_SHELL_COMMAND_QUOTING", "sometimes")]),
+            Err(InvalidEnvVarError {
+                name: "PRINTABLE_SHELL_COMMAND_QUOTING".to_owned(),
+                value: "sometimes".into(),
+                reason: "expected one of: auto, extra-safe".to_owned()
+            })
+        );
+        assert_eq!(
+            from_map(&[("PRINTABLE_SHELL_COMMAND_COLOR", "yes")])
+                .unwrap_err()
+                .to_string(),
+            "Invalid value for environment variable `PRINTABLE_SHELL_COMMAND_COLOR`: \"yes\" \
+             (expected one of: true, false, 1, 0)"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_env_vars_non_utf8() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let value = OsString::from_vec(vec![0xff]);
+        assert_eq!(
+            FormattingOptions::from_env_vars(|_| Some(value.clone())),
+            Err(InvalidEnvVarError {
+                name: "PRINTABLE_SHELL_COMMAND_MAIN_INDENTATION".to_owned(),
+                value,
+                reason: "not valid UTF-8".to_owned()
+            })
+        );
+    }
+}
@@ -0,0 +1,214 @@
+#[cfg(feature = "fingerprint")]
+use std::fmt::Display;
+use std::{
+    collections::BTreeMap,
+    env,
+    ffi::{OsStr, OsString},
+};
+
+#[cfg(feature = "fingerprint")]
+use sha2::{Digest, Sha256};
+
+use crate::PrintableShellCommand;
+
+const SERIALIZATION_HEADER: &[u8] = b"printable-shell-command-fingerprint-v1\0";
+
+/// A SHA-256 hash of the canonical serialization of a command (see
+/// [`PrintableShellCommand::canonical_serialization`]). Requires the
+/// `fingerprint` feature (enabled by default).
+#[cfg(feature = "fingerprint")]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Fingerprint(pub [u8; 32]);
+
+#[cfg(feature = "fingerprint")]
+impl Display for Fingerprint {
+    /// Formats the fingerprint as 64 lowercase hex digits.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_bytes(serialization: &mut Vec<u8>, bytes: &[u8]) {
+    serialization.extend((bytes.len() as u64).to_le_bytes());
+    serialization.extend(bytes);
+}
+
+fn write_optional_bytes(serialization: &mut Vec<u8>, bytes: Option<&[u8]>) {
+    match bytes {
+        None => serialization.push(0),
+        Some(bytes) => {
+            serialization.push(1);
+            write_bytes(serialization, bytes);
+        }
+    }
+}
+
+impl PrintableShellCommand {
+    /// A fingerprint of everything that determines what the command does when
+    /// run: the program, args, env vars that were set or removed for the
+    /// command, whether the environment was cleared (using `env_clear()`),
+    /// and working directory. Arg groups and comments do not affect the
+    /// fingerprint.
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint_with_env_vars::<_, &OsStr>([])
+    }
+
+    /// Like `fingerprint()`, but also includes the value that each of
+    /// `env_var_names` will have for the command (either set for the command,
+    /// or inherited from the current process unless the environment was
+    /// cleared).
+    #[cfg(feature = "fingerprint")]
+    pub fn fingerprint_with_env_vars<I, K>(&self, env_var_names: I) -> Fingerprint
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<OsStr>,
+    {
+        Fingerprint(Sha256::digest(self.canonical_serialization(env_var_names)).into())
+    }
+
+    /// The serialization that is hashed by `fingerprint_with_env_vars(…)`. This
+    /// format is stable across versions of this crate (a new format would use a
+    /// new header). It consists of:
+    ///
+    /// 1. The header `printable-shell-command-fingerprint-v1` followed by a NUL
+    ///    byte.
+    /// 2. The program, as a byte string.
+    /// 3. The number of args (as a `u64`), followed by each arg as a byte
+    ///    string.
+    /// 4. The number of env vars (as a `u64`), followed by each env var in
+    ///    byte order of their names: the name as a byte string, then an
+    ///    optional byte string for the value (unset if the env var is removed,
+    ///    or is not set for the command and not inherited from the current
+    ///    process).
+    /// 5. The working directory, as an optional byte string (unset if the
+    ///    command uses the working directory of the current process). The path
+    ///    is used as given, without being made absolute.
+    /// 6. A `1` byte if the environment was cleared using `env_clear()`, or a
+    ///    `0` byte otherwise.
+    ///
+    /// A byte string is its length (as a `u64`) followed by its bytes, and an
+    /// optional byte string is a `0` byte if unset, or a `1` byte followed by
+    /// a byte string. All integers are little-endian. The bytes of program
+    /// names, args, env vars, and paths are from `OsStr::as_encoded_bytes()`,
+    /// so fingerprints of commands with non-UTF-8 values are only comparable
+    /// on the same platform.
+    pub fn canonical_serialization<I, K>(&self, env_var_names: I) -> Vec<u8>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<OsStr>,
+    {
+        let mut envs: BTreeMap<&[u8], Option<OsString>> = BTreeMap::new();
+        let env_var_names: Vec<K> = env_var_names.into_iter().collect();
+        for key in &env_var_names {
+            let key = key.as_ref();
+            let inherited_value = if self.is_env_cleared() {
+                None
+            } else {
+                env::var_os(key)
+            };
+            envs.insert(key.as_encoded_bytes(), inherited_value);
+        }
+        for (key, value) in self.get_envs() {
+            envs.insert(key.as_encoded_bytes(), value.map(OsStr::to_owned));
+        }
+
+        let mut serialization = SERIALIZATION_HEADER.to_vec();
+        write_bytes(&mut serialization, self.get_program().as_encoded_bytes());
+        serialization.extend((self.get_args().len() as u64).to_le_bytes());
+        for arg in self.get_args() {
+            write_bytes(&mut serialization, arg.as_encoded_bytes());
+        }
+        serialization.extend((envs.len() as u64).to_le_bytes());
+        for (key, value) in &envs {
+            write_bytes(&mut serialization, key);
+            write_optional_bytes(
+                &mut serialization,
+                value.as_deref().map(OsStr::as_encoded_bytes),
+            );
+        }
+        write_optional_bytes(
+            &mut serialization,
+            self.get_current_dir()
+                .map(|current_dir| current_dir.as_os_str().as_encoded_bytes()),
+        );
+        serialization.push(self.is_env_cleared().into());
+        serialization
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PrintableShellCommand;
+
+    #[test]
+    fn canonical_serialization() {
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.arg("-l").current_dir("/tmp");
+        printable_shell_command.env_remove("B").env("A", "1");
+        let mut expected = b"printable-shell-command-fingerprint-v1\0".to_vec();
+        expected.extend(b"\x02\0\0\0\0\0\0\0ls");
+        expected.extend(b"\x01\0\0\0\0\0\0\0\x02\0\0\0\0\0\0\0-l");
+        expected.extend(b"\x02\0\0\0\0\0\0\0");
+        expected.extend(b"\x01\0\0\0\0\0\0\0A\x01\x01\0\0\0\0\0\0\x001");
+        expected.extend(b"\x01\0\0\0\0\0\0\0B\x00");
+        expected.extend(b"\x01\x04\0\0\0\0\0\0\0/tmp");
+        expected.push(0);
+        assert_eq!(
+            printable_shell_command.canonical_serialization::<_, &str>([]),
+            expected
+        );
+
+        printable_shell_command.env_clear().env("A", "1");
+        let serialization = printable_shell_command.canonical_serialization(["PATH"]);
+        assert!(serialization.ends_with(
+            b"A\x01\x01\0\0\0\0\0\0\x001\x04\0\0\0\0\0\0\0PATH\x00\x01\x04\0\0\0\0\0\0\0/tmp\x01"
+        ));
+    }
+
+    #[cfg(feature = "fingerprint")]
+    #[test]
+    fn fingerprint() {
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.args(["hello", "world"]);
+        assert_eq!(
+            printable_shell_command.fingerprint().to_string(),
+            "e8eed5c6bb85d8fc0e2742e233805e4b3c21ec0aa4b5b4214db1481babd01a46"
+        );
+
+        // Grouping does not matter, but arg boundaries do.
+        let mut regrouped = PrintableShellCommand::new("echo");
+        regrouped.arg("hello").arg("world");
+        assert_eq!(
+            regrouped.fingerprint(),
+            printable_shell_command.fingerprint()
+        );
+        let mut joined = PrintableShellCommand::new("echo");
+        joined.arg("hello world");
+        assert_ne!(joined.fingerprint(), printable_shell_command.fingerprint());
+
+        // Env vars from the current process are only included if requested.
+        assert_ne!(
+            printable_shell_command.fingerprint_with_env_vars(["PATH"]),
+            printable_shell_command.fingerprint()
+        );
+        printable_shell_command.env("PATH", "/bin");
+        assert_eq!(
+            printable_shell_command.fingerprint_with_env_vars(["PATH"]),
+            printable_shell_command.fingerprint()
+        );
+
+        // Clearing the environment changes the fingerprint.
+        let mut cleared = PrintableShellCommand::new("echo");
+        cleared.args(["hello", "world"]).env_clear();
+        assert_ne!(
+            cleared.fingerprint(),
+            PrintableShellCommand::new("echo")
+                .args(["hello", "world"])
+                .fingerprint()
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::untrusted_characters::{contains_untrusted_characters, untrusted_character_kind};
+
+/// Characters that are special anywhere in an unquoted fish arg, plus ones
+/// that are special at the start (`~` for home directory expansion, `%` for
+/// process expansion in older versions, and `#` for comments).
+static FISH_ARG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[\s'"\\$*?(){};&|<>^]|^[~%#]"#).unwrap());
+/// `!` is an alias for `not` when used as a command.
+static FISH_PROGRAM_NAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[\s'"\\$*?(){};&|<>^!=]|^[~%#]"#).unwrap());
+
+fn needs_escape(s: &str, is_main_command: bool, sanitize: bool) -> bool {
+    let regex = if is_main_command {
+        &FISH_PROGRAM_NAME_REGEX
+    } else {
+        &FISH_ARG_REGEX
+    };
+    s.is_empty()
+        || regex.is_match(s)
+        || s.chars().any(|c| c.is_control())
+        || (sanitize && contains_untrusted_characters(s))
+}
+
+/// The unquoted backslash escape for `c`, if it must not be written literally.
+///
+/// Unlike POSIX shells, fish has no `$'…'` quoting. Instead, backslash escapes
+/// work outside of quotes.
+fn unquoted_escape(c: char, sanitize: bool) -> Option<String> {
+    match c {
+        '\n' => Some(r"\n".to_owned()),
+        '\r' => Some(r"\r".to_owned()),
+        '\t' => Some(r"\t".to_owned()),
+        '\x1b' => Some(r"\e".to_owned()),
+        '\x00'..='\x1f' | '\x7f' => Some(format!(r"\x{:02x}", c as u32)),
+        c if c.is_control() || (sanitize && untrusted_character_kind(c).is_some()) => {
+            Some(format!(r"\u{:04x}", c as u32))
+        }
+        _ => None,
+    }
+}
+
+/// Quotes using single quotes, in which only `\'` and `\\` are escapes. Any
+/// characters that should not be written literally are written as unquoted
+/// escapes between quoted sections, e.g. `'a'\n'b'`.
+fn quote(s: &str, sanitize: bool) -> String {
+    if s.is_empty() {
+        return "''".to_owned();
+    }
+    let mut quoted = String::new();
+    let mut in_quotes = false;
+    for c in s.chars() {
+        match unquoted_escape(c, sanitize) {
+            Some(escape) => {
+                if in_quotes {
+                    quoted.push('\'');
+                    in_quotes = false;
+                }
+                quoted.push_str(&escape);
+            }
+            None => {
+                if !in_quotes {
+                    quoted.push('\'');
+                    in_quotes = true;
+                }
+                match c {
+                    '\\' => quoted.push_str(r"\\"),
+                    '\'' => quoted.push_str(r"\'"),
+                    c => quoted.push(c),
+                }
+            }
+        }
+    }
+    if in_quotes {
+        quoted.push('\'');
+    }
+    quoted
+}
+
+pub(crate) fn escape_for_fish(
+    s: &str,
+    is_main_command: bool,
+    sanitize: bool,
+    force_quotes: bool,
+) -> String {
+    if force_quotes || needs_escape(s, is_main_command, sanitize) {
+        quote(s, sanitize)
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::escape_for_fish;
+
+    /// Parses a single token using fish's quoting rules (for the subset of
+    /// syntax that `escape_for_fish` produces).
+    fn parse_fish_token(token: &str) -> String {
+        let mut parsed = String::new();
+        let mut chars = token.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\'' => loop {
+                    match chars.next().expect("Unterminated quote") {
+                        '\'' => break,
+                        '\\' => match chars.next().unwrap() {
+                            c @ ('\\' | '\'') => parsed.push(c),
+                            c => {
+                                parsed.push('\\');
+                                parsed.push(c);
+                            }
+                        },
+                        c => parsed.push(c),
+                    }
+                },
+                '\\' => match chars.next().unwrap() {
+                    'n' => parsed.push('\n'),
+                    'r' => parsed.push('\r'),
+                    't' => parsed.push('\t'),
+                    'e' => parsed.push('\x1b'),
+                    'x' => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        parsed.push(u8::from_str_radix(&hex, 16).unwrap() as char);
+                    }
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        parsed
+                            .push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                    }
+                    c => parsed.push(c),
+                },
+                c => {
+                    assert!(
+                        !r#" "$*?(){};&|<>^"#.contains(c),
+                        "Unexpected unquoted special character {:?} in {:?}",
+                        c,
+                        token
+                    );
+                    parsed.push(c)
+                }
+            }
+        }
+        parsed
+    }
+
+    #[test]
+    fn escaping() {
+        let escape = |s| escape_for_fish(s, false, false, false);
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape(""), "''");
+        assert_eq!(escape("it's"), r"'it\'s'");
+        assert_eq!(escape(r"C:\Windows"), r"'C:\\Windows'");
+        assert_eq!(escape("a b"), "'a b'");
+        assert_eq!(escape("~/x"), "'~/x'");
+        assert_eq!(escape("host:~/x"), "host:~/x");
+        assert_eq!(escape("{a,b}"), "'{a,b}'");
+        assert_eq!(escape("hi!there"), "hi!there");
+        assert_eq!(escape("a#b"), "a#b");
+        assert_eq!(escape("#a"), "'#a'");
+        assert_eq!(escape("a\nb"), r"'a'\n'b'");
+        assert_eq!(escape("\x1b[31m"), r"\e'[31m'");
+        assert_eq!(escape_for_fish("plain", false, false, true), "'plain'");
+        assert_eq!(escape_for_fish("!", true, false, false), "'!'");
+        assert_eq!(
+            escape_for_fish("a\u{202e}b", false, true, false),
+            r"'a'\u202e'b'"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in [
+            "plain",
+            "",
+            "it's",
+            r"back\slash\",
+            r"\'",
+            "a b\tc",
+            "line 1\nline 2\n",
+            "\x1b]0;title\x07",
+            "$HOME/*.txt",
+            "(echo hi); true & false | cat > out < in",
+            "~user",
+            "%self",
+            "#comment",
+            "^caret",
+            "trailing\\",
+            "naïve café 🙈",
+        ] {
+            for sanitize in [false, true] {
+                for force_quotes in [false, true] {
+                    assert_eq!(
+                        parse_fish_token(&escape_for_fish(s, false, sanitize, force_quotes)),
+                        s
+                    );
+                }
+            }
+        }
+        assert_eq!(
+            parse_fish_token(&escape_for_fish("a\u{200b}\u{202e}b", false, true, false)),
+            "a\u{200b}\u{202e}b"
+        );
+    }
+}
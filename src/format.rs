@@ -1,30 +1,491 @@
-use std::sync::LazyLock;
+use std::{ffi::OsStr, io, sync::LazyLock};
 
 use regex::Regex;
 
-pub(crate) struct ConditionalEscapeOptions {
+use crate::{
+    fish::escape_for_fish,
+    powershell::escape_for_powershell,
+    untrusted_characters::{
+        contains_untrusted_characters, make_untrusted_characters_visible,
+        replace_untrusted_characters_with_placeholders, untrusted_character_kind,
+    },
+    windows::escape_for_cmd,
+    ShellDialect,
+};
+
+#[derive(Clone, Copy)]
+pub(crate) struct EscapeOptions {
     pub(crate) is_main_command: bool,
+    pub(crate) dialect: ShellDialect,
+    pub(crate) sanitize: bool,
 }
 
 static PROGRAM_NAME_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"[ "'`|$*?><()\[\]{}&\\;#=]"#).unwrap());
+    LazyLock::new(|| Regex::new(r#"[ \t\n\r"'`|$*?><()\[\]{}&\\;#=!]"#).unwrap());
 static ARG_REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r#"[ "'`|$*?><()\[\]{}&\\;#]"#).unwrap());
+    LazyLock::new(|| Regex::new(r#"[ \t\n\r"'`|$*?><()\[\]{}&\\;#]"#).unwrap());
+
+/// Tilde expansion applies in all dialects.
+static POSIX_EXTRA_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"^~"#).unwrap());
+/// History expansion (`!`, and `^` at the start of a line) and job specs (`%`).
+/// Also tilde expansion after `=` or `:` in args that look like assignments
+/// (`PREFIX=~/x`), which `bash` expands outside of POSIX mode.
+static BASH_EXTRA_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^[~^%]|!|^[A-Za-z_][A-Za-z0-9_]*=(.*:)?~"#).unwrap());
+/// Like `bash`, plus `=` expansion (`=ls`) and `^` (used by `EXTENDED_GLOB`).
+/// Assignment-like args are expanded with `MAGIC_EQUAL_SUBST`.
+static ZSH_EXTRA_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^[~%=]|[!^]|^[A-Za-z_][A-Za-z0-9_]*=(.*:)?~"#).unwrap());
+
+pub(crate) static CONTROL_CHARACTER_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[\x00-\x1f\x7f]"#).unwrap());
 
-pub(crate) fn conditional_escape(s: &str, options: ConditionalEscapeOptions) -> String {
+fn needs_sh_escape(s: &str, options: &EscapeOptions, dialect_regex: &Regex) -> bool {
+    if s.is_empty() {
+        return true;
+    }
     let regex = if options.is_main_command {
         &PROGRAM_NAME_REGEX
     } else {
         &ARG_REGEX
     };
+    regex.is_match(s)
+        || dialect_regex.is_match(s)
+        || CONTROL_CHARACTER_REGEX.is_match(s)
+        || (options.sanitize && contains_untrusted_characters(s))
+}
 
-    if regex.is_match(s) {
-        unconditional_escape(s)
+fn conditional_sh_escape(s: &str, options: EscapeOptions, dialect_regex: &Regex) -> String {
+    if needs_sh_escape(s, &options, dialect_regex) {
+        sh_quote(s, options)
     } else {
         s.to_owned()
     }
 }
 
-pub(crate) fn unconditional_escape(s: &str) -> String {
-    format!("'{}'", s.replace("\\", "\\\\").replace("'", "\\'"))
+fn sh_quote(s: &str, options: EscapeOptions) -> String {
+    if options.sanitize && contains_untrusted_characters(s) {
+        return ansi_c_quote(s);
+    }
+    if options.dialect != ShellDialect::Posix && CONTROL_CHARACTER_REGEX.is_match(s) {
+        return ansi_c_quote(s);
+    }
+    single_quote(s)
+}
+
+pub(crate) fn conditional_escape(s: &str, options: EscapeOptions) -> String {
+    match options.dialect {
+        ShellDialect::Posix => conditional_sh_escape(s, options, &POSIX_EXTRA_REGEX),
+        ShellDialect::Bash => conditional_sh_escape(s, options, &BASH_EXTRA_REGEX),
+        ShellDialect::Zsh => conditional_sh_escape(s, options, &ZSH_EXTRA_REGEX),
+        ShellDialect::Fish => escape_for_fish(s, options.is_main_command, options.sanitize, false),
+        ShellDialect::PowerShell => {
+            escape_for_powershell(s, options.is_main_command, options.sanitize, false)
+        }
+        ShellDialect::Cmd => escape_for_cmd(
+            &cmd_placeholders(s, options),
+            options.is_main_command,
+            false,
+        ),
+    }
+}
+
+pub(crate) fn unconditional_escape(s: &str, options: EscapeOptions) -> String {
+    match options.dialect {
+        ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh => sh_quote(s, options),
+        ShellDialect::Fish => escape_for_fish(s, options.is_main_command, options.sanitize, true),
+        ShellDialect::PowerShell => {
+            escape_for_powershell(s, options.is_main_command, options.sanitize, true)
+        }
+        ShellDialect::Cmd => {
+            escape_for_cmd(&cmd_placeholders(s, options), options.is_main_command, true)
+        }
+    }
+}
+
+/// `cmd.exe` has no escapes for untrusted characters, so (when sanitizing)
+/// they are replaced with placeholders before escaping. The placeholders are
+/// escaped like any other text, so the printed command is still safe to paste
+/// (even though it no longer passes the original arg).
+fn cmd_placeholders(s: &str, options: EscapeOptions) -> String {
+    if options.sanitize {
+        replace_untrusted_characters_with_placeholders(s)
+    } else {
+        s.to_owned()
+    }
+}
+
+/// Nothing is special inside single quotes, so a single quote is written by
+/// ending the quoted string, adding an escaped quote, and starting a new one.
+fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r#"'\''"#))
+}
+
+/// `$'…'` quoting, which allows control characters (including newlines) and
+/// other untrusted characters to be written as visible escape sequences.
+fn ansi_c_quote(s: &str) -> String {
+    let mut quoted = "$'".to_owned();
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str(r"\\"),
+            '\'' => quoted.push_str(r"\'"),
+            '\n' => quoted.push_str(r"\n"),
+            '\r' => quoted.push_str(r"\r"),
+            '\t' => quoted.push_str(r"\t"),
+            '\x1b' => quoted.push_str(r"\e"),
+            '\x00'..='\x1f' | '\x7f' => quoted.push_str(&format!(r"\x{:02x}", c as u32)),
+            // `\u…` escapes depend on the locale, so encode the UTF-8 bytes instead.
+            c if untrusted_character_kind(c).is_some() => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    quoted.push_str(&format!(r"\x{:02x}", byte));
+                }
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Formats a comment as a `` `# …` `` command substitution, which expands to
+/// nothing. Characters that could terminate the substitution early (or the
+/// entire line) are neutralized.
+pub(crate) fn comment_substitution(comment: &str, sanitize: bool) -> String {
+    let comment = if sanitize {
+        make_untrusted_characters_visible(comment)
+    } else {
+        comment.to_owned()
+    };
+    let comment = comment
+        .replace("\\", "\\\\")
+        .replace("`", "'")
+        .replace(['\n', '\r'], " ");
+    format!("`# {}`", comment)
+}
+
+static ENV_VAR_NAME_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^[A-Za-z_][A-Za-z0-9_]*$"#).unwrap());
+
+/// Env var names are printed unquoted (e.g. `export NAME=value`, `unset
+/// NAME`), so this returns an error for anything that is not a valid shell
+/// variable name.
+pub(crate) fn check_env_var_name(name: &str) -> io::Result<()> {
+    if ENV_VAR_NAME_REGEX.is_match(name) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid env var name: {:?}", name),
+        ))
+    }
+}
+
+/// `cd dir` for POSIX-like shells, adding `--` if `dir` starts with `-` (so
+/// that it is not read as an option).
+pub(crate) fn sh_cd_statement(dir: &str, escaped_dir: &str) -> String {
+    if dir.starts_with('-') {
+        format!("cd -- {}", escaped_dir)
+    } else {
+        format!("cd {}", escaped_dir)
+    }
+}
+
+/// Like `s.try_into()`, but as an `io::Error` (for functions that can also
+/// fail for other reasons).
+pub(crate) fn os_str_to_str(s: &OsStr) -> io::Result<&str> {
+    s.try_into()
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub(crate) fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders `s` as a JSON string literal (including the quotes).
+pub(crate) fn json_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            '\u{0}'..='\u{1f}' | '\u{7f}' | '\u{2028}' | '\u{2029}' => {
+                quoted += &format!("\\u{:04x}", c as u32)
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Wraps `s` in an HTML `<pre><code>` block, escaping it.
+pub(crate) fn html_code_block(s: &str) -> String {
+    wrap_html_code_block(&html_escape(s))
+}
+
+/// Wraps `html` (which must already be escaped) in an HTML `<pre><code>`
+/// block.
+pub(crate) fn wrap_html_code_block(html: &str) -> String {
+    format!("<pre><code class=\"language-shell\">{}</code></pre>", html)
+}
+
+/// Wraps `s` in a fenced Markdown code block. The fence is made longer than
+/// any run of backticks in `s`, so that it cannot be terminated early.
+pub(crate) fn markdown_code_block(s: &str) -> String {
+    let longest_backtick_run = s
+        .split(|c| c != '`')
+        .map(|run| run.len())
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_backtick_run.max(2) + 1);
+    format!("{}shell\n{}\n{}", fence, s, fence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conditional_escape, json_string, unconditional_escape, EscapeOptions};
+    use crate::ShellDialect;
+
+    fn escape_arg(s: &str, dialect: ShellDialect) -> String {
+        conditional_escape(
+            s,
+            EscapeOptions {
+                is_main_command: false,
+                dialect,
+                sanitize: false,
+            },
+        )
+    }
+
+    fn escape_program(s: &str, dialect: ShellDialect) -> String {
+        conditional_escape(
+            s,
+            EscapeOptions {
+                is_main_command: true,
+                dialect,
+                sanitize: false,
+            },
+        )
+    }
+
+    fn sanitize_arg(s: &str, dialect: ShellDialect) -> String {
+        conditional_escape(
+            s,
+            EscapeOptions {
+                is_main_command: false,
+                dialect,
+                sanitize: true,
+            },
+        )
+    }
+
+    #[test]
+    fn single_quotes() {
+        for dialect in [ShellDialect::Posix, ShellDialect::Bash, ShellDialect::Zsh] {
+            assert_eq!(escape_arg("it's", dialect), r#"'it'\''s'"#);
+            assert_eq!(escape_arg(r"C:\Windows", dialect), r"'C:\Windows'");
+            assert_eq!(escape_arg("", dialect), "''");
+            assert_eq!(
+                unconditional_escape(
+                    "plain",
+                    EscapeOptions {
+                        is_main_command: false,
+                        dialect,
+                        sanitize: false
+                    }
+                ),
+                "'plain'"
+            );
+        }
+    }
+
+    #[test]
+    fn history_expansion() {
+        assert_eq!(escape_arg("hi!there", ShellDialect::Posix), "hi!there");
+        assert_eq!(escape_arg("hi!there", ShellDialect::Bash), "'hi!there'");
+        assert_eq!(escape_arg("hi!there", ShellDialect::Zsh), "'hi!there'");
+        assert_eq!(escape_program("!", ShellDialect::Posix), "'!'");
+    }
+
+    #[test]
+    fn quick_substitution() {
+        assert_eq!(escape_arg("^foo^bar", ShellDialect::Posix), "^foo^bar");
+        assert_eq!(escape_arg("^foo^bar", ShellDialect::Bash), "'^foo^bar'");
+        assert_eq!(escape_program("^foo^bar", ShellDialect::Bash), "'^foo^bar'");
+        assert_eq!(escape_arg("a^b", ShellDialect::Bash), "a^b");
+        assert_eq!(escape_arg("a^b", ShellDialect::Zsh), "'a^b'");
+    }
+
+    #[test]
+    fn leading_tilde() {
+        for dialect in [ShellDialect::Posix, ShellDialect::Bash, ShellDialect::Zsh] {
+            assert_eq!(escape_arg("~/.config", dialect), "'~/.config'");
+            assert_eq!(escape_arg("~user", dialect), "'~user'");
+            assert_eq!(escape_arg("host:~/dir", dialect), "host:~/dir");
+        }
+        assert_eq!(escape_arg("PREFIX=~/x", ShellDialect::Posix), "PREFIX=~/x");
+        for dialect in [ShellDialect::Bash, ShellDialect::Zsh] {
+            assert_eq!(escape_arg("PREFIX=~/x", dialect), "'PREFIX=~/x'");
+            assert_eq!(escape_arg("PATH=/bin:~/bin", dialect), "'PATH=/bin:~/bin'");
+            assert_eq!(escape_arg("--prefix=~/x", dialect), "--prefix=~/x");
+            assert_eq!(escape_arg("a=b~c", dialect), "a=b~c");
+        }
+    }
+
+    #[test]
+    fn job_specs() {
+        assert_eq!(escape_arg("%1", ShellDialect::Posix), "%1");
+        assert_eq!(escape_arg("%1", ShellDialect::Bash), "'%1'");
+        assert_eq!(escape_arg("%1", ShellDialect::Zsh), "'%1'");
+        assert_eq!(escape_arg("100%", ShellDialect::Bash), "100%");
+    }
+
+    #[test]
+    fn equals_expansion() {
+        assert_eq!(escape_arg("=ls", ShellDialect::Bash), "=ls");
+        assert_eq!(escape_arg("=ls", ShellDialect::Zsh), "'=ls'");
+    }
+
+    #[test]
+    fn newlines_and_control_characters() {
+        assert_eq!(escape_arg("a\nb", ShellDialect::Posix), "'a\nb'");
+        assert_eq!(escape_arg("a\nb", ShellDialect::Bash), r"$'a\nb'");
+        assert_eq!(escape_arg("a\tb", ShellDialect::Zsh), r"$'a\tb'");
+        assert_eq!(
+            escape_arg("it's\r\x1b[31m\x07\\", ShellDialect::Bash),
+            r"$'it\'s\r\e[31m\x07\\'"
+        );
+    }
+
+    #[test]
+    fn sanitize() {
+        for dialect in [ShellDialect::Posix, ShellDialect::Bash, ShellDialect::Zsh] {
+            assert_eq!(
+                sanitize_arg("\x1b]0;pwned\x07", dialect),
+                r"$'\e]0;pwned\x07'"
+            );
+            assert_eq!(
+                sanitize_arg("safe.txt\rrm -rf ~", dialect),
+                r"$'safe.txt\rrm -rf ~'"
+            );
+            assert_eq!(
+                sanitize_arg("access\u{202e}\u{2066}level", dialect),
+                r"$'access\xe2\x80\xae\xe2\x81\xa6level'"
+            );
+            assert_eq!(
+                sanitize_arg("zero\u{200b}width", dialect),
+                r"$'zero\xe2\x80\x8bwidth'"
+            );
+            assert_eq!(sanitize_arg("\u{9b}31m", dialect), r"$'\xc2\x9b31m'");
+            assert_eq!(sanitize_arg("ordinary", dialect), "ordinary");
+            assert_eq!(sanitize_arg("naïve café", dialect), "'naïve café'");
+        }
+        assert_eq!(
+            escape_arg("access\u{202e}level", ShellDialect::Bash),
+            "access\u{202e}level"
+        );
+        assert_eq!(escape_arg("a\x1bb", ShellDialect::Posix), "'a\x1bb'");
+        assert_eq!(
+            sanitize_arg("access\u{202e}level\r\x1b", ShellDialect::Cmd),
+            "access^<U+202E^>level^<U+000D^>^<U+001B^>"
+        );
+        assert_eq!(
+            escape_arg("access\u{202e}level", ShellDialect::Cmd),
+            "access\u{202e}level"
+        );
+    }
+
+    #[test]
+    fn cmd() {
+        assert_eq!(escape_program("tool.exe", ShellDialect::Cmd), "tool.exe");
+        assert_eq!(
+            escape_program(r"C:\Program Files\tool.exe", ShellDialect::Cmd),
+            r#"^"C:\Program Files\tool.exe^""#
+        );
+        assert_eq!(escape_arg("/v", ShellDialect::Cmd), "/v");
+        assert_eq!(escape_arg("a&b", ShellDialect::Cmd), "a^&b");
+        assert_eq!(
+            escape_arg(r#"say "100%""#, ShellDialect::Cmd),
+            r#"^"say \^"100^%\^"^""#
+        );
+    }
+
+    #[test]
+    fn powershell() {
+        assert_eq!(escape_program("git", ShellDialect::PowerShell), "git");
+        assert_eq!(
+            escape_program(
+                r"C:\Program Files\Git\bin\git.exe",
+                ShellDialect::PowerShell
+            ),
+            r"& 'C:\Program Files\Git\bin\git.exe'"
+        );
+        assert_eq!(escape_arg("it's", ShellDialect::PowerShell), "'it''s'");
+        assert_eq!(escape_arg("$HOME", ShellDialect::PowerShell), "'$HOME'");
+        assert_eq!(escape_arg("a$b", ShellDialect::PowerShell), "'a$b'");
+        assert_eq!(escape_arg("@args", ShellDialect::PowerShell), "'@args'");
+        assert_eq!(
+            escape_arg("user@host", ShellDialect::PowerShell),
+            "user@host"
+        );
+        assert_eq!(escape_arg("--%", ShellDialect::PowerShell), "'--%'");
+        assert_eq!(escape_arg("--", ShellDialect::PowerShell), "'--'");
+        assert_eq!(
+            escape_arg("--verbose", ShellDialect::PowerShell),
+            "--verbose"
+        );
+        assert_eq!(
+            escape_arg("-filter:v", ShellDialect::PowerShell),
+            "'-filter:v'"
+        );
+        assert_eq!(
+            escape_arg("-D.key=1", ShellDialect::PowerShell),
+            "'-D.key=1'"
+        );
+        assert_eq!(escape_arg("a,b", ShellDialect::PowerShell), "'a,b'");
+        assert_eq!(escape_arg("#hi", ShellDialect::PowerShell), "'#hi'");
+        assert_eq!(escape_arg("100%", ShellDialect::PowerShell), "100%");
+        assert_eq!(
+            escape_arg("‘quoted’", ShellDialect::PowerShell),
+            "'‘‘quoted’’'"
+        );
+        assert_eq!(escape_arg("", ShellDialect::PowerShell), "''");
+        assert_eq!(
+            escape_arg("a\nb`$c\"", ShellDialect::PowerShell),
+            r#""a`nb```$c`"""#
+        );
+        assert_eq!(
+            sanitize_arg("x\u{202e}\x1by", ShellDialect::PowerShell),
+            r#""x`u{202e}`ey""#
+        );
+    }
+
+    #[test]
+    fn fish() {
+        assert_eq!(escape_arg("it's", ShellDialect::Fish), r"'it\'s'");
+        assert_eq!(
+            escape_arg(r"C:\Windows", ShellDialect::Fish),
+            r"'C:\\Windows'"
+        );
+        assert_eq!(escape_arg("hi!there", ShellDialect::Fish), "hi!there");
+        assert_eq!(escape_arg("a\nb", ShellDialect::Fish), r"'a'\n'b'");
+        assert_eq!(escape_arg("~/x", ShellDialect::Fish), "'~/x'");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(json_string("ffmpeg"), r#""ffmpeg""#);
+        assert_eq!(
+            json_string("say \"hi\"\\\n\t\x1b\u{2028}é"),
+            r#""say \"hi\"\\\n\t\u001b\u2028é""#
+        );
+    }
 }
@@ -1,3 +1,35 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+/// A string could not be parsed as a formatting option value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseOptionError {
+    pub value: String,
+    /// The values that are accepted.
+    pub expected: &'static [&'static str],
+}
+
+impl ParseOptionError {
+    pub(crate) fn new(value: &str, expected: &'static [&'static str]) -> Self {
+        Self {
+            value: value.to_owned(),
+            expected,
+        }
+    }
+}
+
+impl Display for ParseOptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid value {:?} (expected one of: {})",
+            self.value,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl Error for ParseOptionError {}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Quoting {
     /// Quote only arguments that need it for safety. This tries to be
@@ -10,6 +42,19 @@ pub enum Quoting {
     ExtraSafe,
 }
 
+impl FromStr for Quoting {
+    type Err = ParseOptionError;
+
+    /// Parses `auto` or `extra-safe`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "extra-safe" => Ok(Self::ExtraSafe),
+            _ => Err(ParseOptionError::new(s, &["auto", "extra-safe"])),
+        }
+    }
+}
+
 impl Default for Quoting {
     fn default() -> Self {
         Self::Auto
@@ -24,13 +69,166 @@ pub enum ArgumentLineWrapping {
     Inline,
 }
 
+impl FromStr for ArgumentLineWrapping {
+    type Err = ParseOptionError;
+
+    /// Parses `by-entry`, `nested-by-entry`, `by-argument`, or `inline`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "by-entry" => Ok(Self::ByEntry),
+            "nested-by-entry" => Ok(Self::NestedByEntry),
+            "by-argument" => Ok(Self::ByArgument),
+            "inline" => Ok(Self::Inline),
+            _ => Err(ParseOptionError::new(
+                s,
+                &["by-entry", "nested-by-entry", "by-argument", "inline"],
+            )),
+        }
+    }
+}
+
 impl Default for ArgumentLineWrapping {
     fn default() -> Self {
         Self::ByEntry
     }
 }
 
-#[derive(Default)]
+/// The shell that printed commands are meant to be pasted into.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ShellDialect {
+    /// POSIX `sh`, used non-interactively (e.g. in a script).
+    Posix,
+    /// Interactive `bash`. Compared to `Posix`, this also protects against
+    /// history expansion (`!`, `^`) and job specs (`%`), and uses `$'…'` for
+    /// args with newlines or other control characters.
+    Bash,
+    /// Interactive `zsh`. Like `Bash`, and additionally protects against `=`
+    /// expansion and `EXTENDED_GLOB` patterns. Interactive zsh runs `#` as a
+    /// command unless `INTERACTIVE_COMMENTS` is set, so arg group comments
+    /// are omitted.
+    Zsh,
+    /// The `fish` shell. Single quotes allow `\'` and `\\` escapes, and
+    /// control characters are written as backslash escapes between quoted
+    /// sections (e.g. `'a'\n'b'`), since fish does not support `$'…'`. fish
+    /// has no inline comments, so arg group comments are omitted.
+    Fish,
+    /// PowerShell. Args are single-quoted (or double-quoted with `` ` ``
+    /// escapes when they contain control characters), a quoted program name is
+    /// preceded by `&`, and lines are continued with `` ` ``. Comments use
+    /// `<# … #>`.
+    PowerShell,
+    /// Windows `cmd.exe`. Args are quoted using the rules that Windows programs
+    /// use to split their command line (`CommandLineToArgvW`), and then all
+    /// `cmd.exe` metacharacters are escaped with `^`. Lines are continued with
+    /// `^`, and arg group comments are omitted.
+    ///
+    /// Untrusted characters cannot be escaped for `cmd.exe`, so
+    /// `sanitize_untrusted_characters` replaces them with visible placeholders
+    /// like `<U+202E>` (which means the printed command no longer passes the
+    /// original args).
+    Cmd,
+}
+
+impl FromStr for ShellDialect {
+    type Err = ParseOptionError;
+
+    /// Parses `posix`, `bash`, `zsh`, `fish`, `powershell`, or `cmd`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "posix" => Ok(Self::Posix),
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            "powershell" => Ok(Self::PowerShell),
+            "cmd" => Ok(Self::Cmd),
+            _ => Err(ParseOptionError::new(
+                s,
+                &["posix", "bash", "zsh", "fish", "powershell", "cmd"],
+            )),
+        }
+    }
+}
+
+impl Default for ShellDialect {
+    fn default() -> Self {
+        Self::Posix
+    }
+}
+
+/// Rewrites absolute path args into shorter forms that the shell expands back
+/// into the same path.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PathAbbreviation {
+    None,
+    /// Print args under `$HOME` as `~/…`.
+    Home,
+    /// Additionally, print args under the command's working directory as
+    /// `./…`. (This takes precedence over `~/…`.)
+    HomeAndWorkingDirectory,
+}
+
+impl FromStr for PathAbbreviation {
+    type Err = ParseOptionError;
+
+    /// Parses `none`, `home`, or `home-and-working-directory`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "home" => Ok(Self::Home),
+            "home-and-working-directory" => Ok(Self::HomeAndWorkingDirectory),
+            _ => Err(ParseOptionError::new(
+                s,
+                &["none", "home", "home-and-working-directory"],
+            )),
+        }
+    }
+}
+
+impl Default for PathAbbreviation {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// How to group the args of a plain `Command`, which does not record groups.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArgGrouping {
+    /// Each arg is its own group.
+    Separate,
+    /// A flag (an arg starting with `-`) is grouped with the following arg if
+    /// that arg does not start with `-`. Flags that contain `=` are not grouped,
+    /// and all args after `--` are separate.
+    Heuristic,
+    /// Use the [`CommandSchema`](crate::CommandSchema) for the program to
+    /// decide which flags take a value. Falls back to `Heuristic` for programs
+    /// without a schema and for flags that are not in the schema.
+    Schema,
+}
+
+impl FromStr for ArgGrouping {
+    type Err = ParseOptionError;
+
+    /// Parses `separate`, `heuristic`, or `schema`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "separate" => Ok(Self::Separate),
+            "heuristic" => Ok(Self::Heuristic),
+            "schema" => Ok(Self::Schema),
+            _ => Err(ParseOptionError::new(
+                s,
+                &["separate", "heuristic", "schema"],
+            )),
+        }
+    }
+}
+
+impl Default for ArgGrouping {
+    fn default() -> Self {
+        Self::Separate
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct FormattingOptions {
     pub main_indentation: Option<String>,
     pub arg_indentation: Option<String>,
@@ -38,5 +236,189 @@ pub struct FormattingOptions {
     // Line wrapping to use between arguments.
     pub argument_line_wrapping: Option<ArgumentLineWrapping>,
     pub skip_line_wrap_before_first_arg: Option<bool>,
-    // TODO: text styling
+    /// Wrap arguments that refer to existing paths in OSC 8 terminal hyperlinks.
+    pub hyperlink_paths: Option<bool>,
+    /// Abbreviate absolute path args as `~/…` or `./…` where equivalent.
+    pub path_abbreviation: Option<PathAbbreviation>,
+    /// The shell that the output should be valid for. Defaults to
+    /// `ShellDialect::Posix`.
+    pub dialect: Option<ShellDialect>,
+    /// Always render control, bidirectional formatting, and zero-width
+    /// characters as visible escapes (using `$'…'`, even for
+    /// `ShellDialect::Posix`).
+    pub sanitize_untrusted_characters: Option<bool>,
+    /// How to split the args of a plain `Command` into groups. (Arg groups of a `PrintableShellCommand` are always kept.)
+    pub arg_grouping: Option<ArgGrouping>,
+    /// Style the program (bold), flags (cyan), and comments (dim) with ANSI
+    /// escape codes.
+    pub color: Option<bool>,
+}
+
+impl FormattingOptions {
+    /// Equivalent to `FormattingOptions::default()`, for use as the start of a
+    /// chain of builder calls.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn main_indentation(mut self, main_indentation: impl Into<String>) -> Self {
+        self.main_indentation = Some(main_indentation.into());
+        self
+    }
+
+    pub fn arg_indentation(mut self, arg_indentation: impl Into<String>) -> Self {
+        self.arg_indentation = Some(arg_indentation.into());
+        self
+    }
+
+    pub fn quoting(mut self, quoting: Quoting) -> Self {
+        self.quoting = Some(quoting);
+        self
+    }
+
+    pub fn argument_line_wrapping(mut self, argument_line_wrapping: ArgumentLineWrapping) -> Self {
+        self.argument_line_wrapping = Some(argument_line_wrapping);
+        self
+    }
+
+    pub fn skip_line_wrap_before_first_arg(
+        mut self,
+        skip_line_wrap_before_first_arg: bool,
+    ) -> Self {
+        self.skip_line_wrap_before_first_arg = Some(skip_line_wrap_before_first_arg);
+        self
+    }
+
+    pub fn hyperlink_paths(mut self, hyperlink_paths: bool) -> Self {
+        self.hyperlink_paths = Some(hyperlink_paths);
+        self
+    }
+
+    pub fn path_abbreviation(mut self, path_abbreviation: PathAbbreviation) -> Self {
+        self.path_abbreviation = Some(path_abbreviation);
+        self
+    }
+
+    pub fn dialect(mut self, dialect: ShellDialect) -> Self {
+        self.dialect = Some(dialect);
+        self
+    }
+
+    pub fn sanitize_untrusted_characters(mut self, sanitize_untrusted_characters: bool) -> Self {
+        self.sanitize_untrusted_characters = Some(sanitize_untrusted_characters);
+        self
+    }
+
+    pub fn arg_grouping(mut self, arg_grouping: ArgGrouping) -> Self {
+        self.arg_grouping = Some(arg_grouping);
+        self
+    }
+
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Prints the entire command on a single line.
+    pub fn compact() -> Self {
+        Self::new().argument_line_wrapping(ArgumentLineWrapping::Inline)
+    }
+
+    /// Keeps the program and its first arg group on the same line, so that
+    /// searching a log for the program name also shows how it was called.
+    pub fn ci_log() -> Self {
+        Self::new()
+            .argument_line_wrapping(ArgumentLineWrapping::ByEntry)
+            .skip_line_wrap_before_first_arg(true)
+    }
+
+    /// Prints one arg group per line, with explicit (default) indentation.
+    pub fn documentation() -> Self {
+        Self::new()
+            .main_indentation("")
+            .arg_indentation("  ")
+            .argument_line_wrapping(ArgumentLineWrapping::ByEntry)
+            .skip_line_wrap_before_first_arg(false)
+    }
+
+    /// Quotes every argument (see [`Quoting::ExtraSafe`]) and escapes untrusted
+    /// characters (see [`FormattingOptions::sanitize_untrusted_characters`]).
+    pub fn extra_safe() -> Self {
+        Self::new()
+            .quoting(Quoting::ExtraSafe)
+            .sanitize_untrusted_characters(true)
+    }
+
+    /// Layers `overrides` on top of `self`: any field that is set in
+    /// `overrides` takes precedence, and all other fields are kept from `self`.
+    ///
+    /// This allows library defaults to be combined with user preferences, e.g.
+    /// `FormattingOptions::ci_log().merge(user_options)`.
+    pub fn merge(self, overrides: FormattingOptions) -> Self {
+        Self {
+            main_indentation: overrides.main_indentation.or(self.main_indentation),
+            arg_indentation: overrides.arg_indentation.or(self.arg_indentation),
+            quoting: overrides.quoting.or(self.quoting),
+            argument_line_wrapping: overrides
+                .argument_line_wrapping
+                .or(self.argument_line_wrapping),
+            skip_line_wrap_before_first_arg: overrides
+                .skip_line_wrap_before_first_arg
+                .or(self.skip_line_wrap_before_first_arg),
+            hyperlink_paths: overrides.hyperlink_paths.or(self.hyperlink_paths),
+            path_abbreviation: overrides.path_abbreviation.or(self.path_abbreviation),
+            dialect: overrides.dialect.or(self.dialect),
+            sanitize_untrusted_characters: overrides
+                .sanitize_untrusted_characters
+                .or(self.sanitize_untrusted_characters),
+            arg_grouping: overrides.arg_grouping.or(self.arg_grouping),
+            color: overrides.color.or(self.color),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ArgumentLineWrapping, FormattingOptions, Quoting};
+
+    #[test]
+    fn builder() {
+        assert_eq!(
+            FormattingOptions::new()
+                .quoting(Quoting::ExtraSafe)
+                .arg_indentation("\t"),
+            FormattingOptions {
+                quoting: Some(Quoting::ExtraSafe),
+                arg_indentation: Some("\t".to_owned()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let merged = FormattingOptions::compact().merge(FormattingOptions::extra_safe());
+        assert_eq!(
+            merged,
+            FormattingOptions {
+                argument_line_wrapping: Some(ArgumentLineWrapping::Inline),
+                quoting: Some(Quoting::ExtraSafe),
+                sanitize_untrusted_characters: Some(true),
+                ..Default::default()
+            }
+        );
+
+        let merged = FormattingOptions::compact().merge(
+            FormattingOptions::new().argument_line_wrapping(ArgumentLineWrapping::ByArgument),
+        );
+        assert_eq!(
+            merged.argument_line_wrapping,
+            Some(ArgumentLineWrapping::ByArgument)
+        );
+
+        assert_eq!(
+            FormattingOptions::documentation().merge(FormattingOptions::default()),
+            FormattingOptions::documentation()
+        );
+    }
 }
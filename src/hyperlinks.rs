@@ -0,0 +1,157 @@
+#[cfg(not(windows))]
+use std::sync::LazyLock;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const OSC_8_START: &str = "\x1b]8;;";
+const STRING_TERMINATOR: &str = "\x1b\\";
+
+#[cfg(not(windows))]
+static HOSTNAME: LazyLock<String> = LazyLock::new(hostname);
+
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    // SAFETY: `buffer` is valid for writes of `buffer.len()` bytes.
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+    let length = buffer.iter().position(|b| *b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+#[cfg(not(any(unix, windows)))]
+fn hostname() -> String {
+    String::new()
+}
+
+/// Percent-encodes everything except unreserved characters, `/`, and `:`.
+fn percent_encode_path(path: &[u8]) -> String {
+    let mut encoded = String::new();
+    for byte in path {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(not(windows))]
+fn file_url(absolute_path: &Path) -> String {
+    format!(
+        "file://{}{}",
+        *HOSTNAME,
+        percent_encode_path(absolute_path.as_os_str().as_encoded_bytes())
+    )
+}
+
+#[cfg(windows)]
+fn file_url(absolute_path: &Path) -> String {
+    windows_file_url(&absolute_path.to_string_lossy())
+}
+
+/// The `file:` URL for an absolute Windows path (as returned by
+/// `fs::canonicalize`). `\\?\C:\a` becomes `file:///C:/a`, and
+/// `\\?\UNC\server\share\a` becomes `file://server/share/a`.
+#[cfg(any(windows, test))]
+fn windows_file_url(path: &str) -> String {
+    let path = path.strip_prefix(r"\\?\").unwrap_or(path);
+    let path = path.replace('\\', "/");
+    if let Some(unc_path) = path.strip_prefix("UNC/") {
+        format!("file://{}", percent_encode_path(unc_path.as_bytes()))
+    } else if let Some(unc_path) = path.strip_prefix("//") {
+        format!("file://{}", percent_encode_path(unc_path.as_bytes()))
+    } else {
+        format!("file:///{}", percent_encode_path(path.as_bytes()))
+    }
+}
+
+/// Returns a `file://` URL if `arg` refers to an existing path (resolved
+/// relative to `working_directory`, if given). Args that start with `-` are
+/// never linked, since they are almost always flags.
+pub(crate) fn file_url_for_arg(arg: &str, working_directory: Option<&Path>) -> Option<String> {
+    if arg.is_empty() || arg.starts_with('-') {
+        return None;
+    }
+    let path: PathBuf = match working_directory {
+        Some(working_directory) => working_directory.join(arg),
+        None => arg.into(),
+    };
+    let absolute_path = fs::canonicalize(path).ok()?;
+    Some(file_url(&absolute_path))
+}
+
+/// Wraps `text` in an OSC 8 hyperlink. Terminals that support OSC 8 display
+/// only `text` (and copy only `text` when it is selected); other terminals
+/// generally ignore the escape sequences.
+pub(crate) fn osc_8_hyperlink(text: &str, url: &str) -> String {
+    format!(
+        "{}{}{}{}{}{}",
+        OSC_8_START, url, STRING_TERMINATOR, text, OSC_8_START, STRING_TERMINATOR
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{file_url_for_arg, osc_8_hyperlink, percent_encode_path, windows_file_url};
+
+    #[test]
+    fn percent_encoding() {
+        assert_eq!(
+            percent_encode_path(b"/tmp/My video (slow-mo).mov"),
+            "/tmp/My%20video%20%28slow-mo%29.mov"
+        );
+    }
+
+    #[test]
+    fn windows_file_urls() {
+        assert_eq!(
+            windows_file_url(r"\\?\C:\Users\me\My file.txt"),
+            "file:///C:/Users/me/My%20file.txt"
+        );
+        assert_eq!(windows_file_url(r"D:\a.txt"), "file:///D:/a.txt");
+        assert_eq!(
+            windows_file_url(r"\\?\UNC\server\share\a.txt"),
+            "file://server/share/a.txt"
+        );
+        assert_eq!(
+            windows_file_url(r"\\server\share\a.txt"),
+            "file://server/share/a.txt"
+        );
+    }
+
+    #[test]
+    fn file_urls() {
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        let url = file_url_for_arg("Cargo.toml", Some(Path::new(manifest_dir))).unwrap();
+        assert!(url.starts_with("file://"));
+        assert!(url.ends_with("/Cargo.toml"));
+        assert_eq!(
+            file_url_for_arg("does-not-exist.txt", Some(Path::new(manifest_dir))),
+            None
+        );
+
+        // Flags are not linked, even if a file with the same name exists.
+        let dir = std::env::temp_dir().join("printable-shell-command-hyperlinks-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("-v"), "").unwrap();
+        assert_eq!(file_url_for_arg("-v", Some(&dir)), None);
+        assert!(file_url_for_arg("./-v", Some(&dir)).is_some());
+    }
+
+    #[test]
+    fn hyperlink() {
+        assert_eq!(
+            osc_8_hyperlink("./a.txt", "file://host/tmp/a.txt"),
+            "\x1b]8;;file://host/tmp/a.txt\x1b\\./a.txt\x1b]8;;\x1b\\"
+        );
+    }
+}
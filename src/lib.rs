@@ -1,10 +1,50 @@
+mod arg_grouping;
+mod arg_max;
+mod cmd_macro;
 mod command;
+mod command_diff;
+mod command_schema;
+mod env_defaults;
+mod fingerprint;
+mod fish;
 mod format;
 mod formatting_options;
+mod hyperlinks;
+mod path_abbreviation;
+mod powershell;
 mod print_builder;
 mod printable_shell_command;
+mod recorder;
+mod runner;
+mod sequence_diff;
 mod shell_printable;
+mod shell_script;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+mod untrusted_characters;
+mod windows;
+mod wrappers;
 
-pub use formatting_options::{ArgumentLineWrapping, FormattingOptions, Quoting};
+pub use arg_max::{arg_max, ArgMaxExceededError};
+pub use command_diff::{CommandDiff, DiffEntry};
+pub use command_schema::{CommandSchema, SchemaWarning};
+pub use env_defaults::InvalidEnvVarError;
+#[cfg(feature = "fingerprint")]
+pub use fingerprint::Fingerprint;
+pub use formatting_options::{
+    ArgGrouping, ArgumentLineWrapping, FormattingOptions, ParseOptionError, PathAbbreviation,
+    Quoting, ShellDialect,
+};
 pub use printable_shell_command::PrintableShellCommand;
+pub use recorder::{RecordedCommand, Recording};
+#[cfg(any(unix, windows))]
+pub use runner::{fake_exit_status, fake_output};
+pub use runner::{
+    ArgPattern, ArgvPattern, CommandRunner, FakeCommandRunner, FakeInvocation, RealCommandRunner,
+};
 pub use shell_printable::{ShellPrintable, ShellPrintableWithOptions};
+pub use shell_script::ShellScript;
+pub use untrusted_characters::{
+    UntrustedCharacter, UntrustedCharacterKind, UntrustedCharacterLocation,
+};
+pub use wrappers::ContainerEngine;
@@ -0,0 +1,126 @@
+use std::{env, path::Path};
+
+use crate::PathAbbreviation;
+
+/// Splits `arg` into a prefix that must be printed unquoted (`~`, `~/`, `.`,
+/// or `./`) and a remainder that can be escaped as usual.
+///
+/// An abbreviation is only returned if the printed form expands to the same
+/// path for the executing process: `~` is expanded by the shell using `home`
+/// (see `PrintBuilder::set_home_override`), and relative paths are resolved
+/// against the command's working directory.
+pub(crate) fn abbreviate_path<'a>(
+    arg: &'a str,
+    path_abbreviation: PathAbbreviation,
+    home: Option<&str>,
+    working_directory: Option<&Path>,
+) -> Option<(&'static str, &'a str)> {
+    let working_directory = match path_abbreviation {
+        PathAbbreviation::HomeAndWorkingDirectory => {
+            env::current_dir()
+                .ok()
+                .map(|current_dir| match working_directory {
+                    Some(working_directory) => current_dir.join(working_directory),
+                    None => current_dir,
+                })
+        }
+        _ => None,
+    };
+    abbreviate_path_with(
+        arg,
+        path_abbreviation,
+        home,
+        working_directory.as_ref().and_then(|path| path.to_str()),
+    )
+}
+
+fn abbreviate_path_with<'a>(
+    arg: &'a str,
+    path_abbreviation: PathAbbreviation,
+    home: Option<&str>,
+    working_directory: Option<&str>,
+) -> Option<(&'static str, &'a str)> {
+    if path_abbreviation == PathAbbreviation::None {
+        return None;
+    }
+    if path_abbreviation == PathAbbreviation::HomeAndWorkingDirectory {
+        if let Some(rest) = working_directory.and_then(|dir| strip_directory(arg, dir)) {
+            return Some(if rest.is_empty() {
+                (".", "")
+            } else {
+                ("./", rest)
+            });
+        }
+    }
+    let rest = home.and_then(|home| strip_directory(arg, home))?;
+    Some(if rest.is_empty() {
+        ("~", "")
+    } else {
+        ("~/", rest)
+    })
+}
+
+/// Compares textually (rather than by path components), so that the remainder
+/// is preserved exactly (e.g. including any trailing slash).
+fn strip_directory<'a>(arg: &'a str, directory: &str) -> Option<&'a str> {
+    if !directory.starts_with('/') {
+        return None;
+    }
+    let directory = directory.trim_end_matches('/');
+    let rest = arg.strip_prefix(directory)?;
+    if rest.is_empty() {
+        return Some("");
+    }
+    rest.strip_prefix('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::abbreviate_path_with;
+    use crate::PathAbbreviation;
+
+    #[test]
+    fn home() {
+        let abbreviate = |arg| {
+            abbreviate_path_with(
+                arg,
+                PathAbbreviation::Home,
+                Some("/home/me"),
+                Some("/home/me/src"),
+            )
+        };
+        assert_eq!(abbreviate("/home/me/.config/"), Some(("~/", ".config/")));
+        assert_eq!(abbreviate("/home/me"), Some(("~", "")));
+        assert_eq!(
+            abbreviate("/home/me/src/main.rs"),
+            Some(("~/", "src/main.rs"))
+        );
+        assert_eq!(abbreviate("/home/meow/.config"), None);
+        assert_eq!(abbreviate("home/me/.config"), None);
+        assert_eq!(abbreviate("-/home/me/.config"), None);
+    }
+
+    #[test]
+    fn working_directory() {
+        let abbreviate = |arg| {
+            abbreviate_path_with(
+                arg,
+                PathAbbreviation::HomeAndWorkingDirectory,
+                Some("/home/me/"),
+                Some("/home/me/src"),
+            )
+        };
+        assert_eq!(abbreviate("/home/me/src/main.rs"), Some(("./", "main.rs")));
+        assert_eq!(abbreviate("/home/me/src"), Some((".", "")));
+        assert_eq!(abbreviate("/home/me/src2"), Some(("~/", "src2")));
+        assert_eq!(abbreviate("/etc/hosts"), None);
+    }
+
+    #[test]
+    fn none() {
+        assert_eq!(
+            abbreviate_path_with("/home/me/x", PathAbbreviation::None, Some("/home/me"), None),
+            None
+        );
+    }
+}
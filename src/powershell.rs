@@ -0,0 +1,116 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::{
+    format::CONTROL_CHARACTER_REGEX,
+    untrusted_characters::{
+        contains_untrusted_characters, make_untrusted_characters_visible, untrusted_character_kind,
+    },
+};
+
+/// PowerShell treats typographic quotes like their ASCII equivalents.
+const SINGLE_QUOTES: &[char] = &['\'', '‘', '’', '‚', '‛'];
+const DOUBLE_QUOTES: &[char] = &['"', '“', '”', '„'];
+
+/// Characters that are special anywhere in an unquoted arg. (`*`, `?`, and `[`
+/// are included because PowerShell on Unix expands globs for native commands.)
+static POWERSHELL_ARG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"[\s'‘’‚‛"“”„`$(){};,|&<>*?\[\]]|^[@#~]|^--%?$"#).unwrap());
+/// PowerShell may split or reinterpret args that look like parameters (e.g.
+/// `-filter:v` or `-D.key=1`), so only plain flags are left unquoted.
+static POWERSHELL_PLAIN_FLAG_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"^--?[A-Za-z0-9_][A-Za-z0-9_-]*$"#).unwrap());
+
+fn needs_escape(s: &str, sanitize: bool) -> bool {
+    s.is_empty()
+        || POWERSHELL_ARG_REGEX.is_match(s)
+        || (s.starts_with('-') && !POWERSHELL_PLAIN_FLAG_REGEX.is_match(s))
+        || CONTROL_CHARACTER_REGEX.is_match(s)
+        || (sanitize && contains_untrusted_characters(s))
+}
+
+/// Single-quoted strings are verbatim, except that single quotes (of any kind)
+/// are doubled.
+fn single_quote(s: &str) -> String {
+    let mut quoted = "'".to_owned();
+    for c in s.chars() {
+        if SINGLE_QUOTES.contains(&c) {
+            quoted.push(c);
+        }
+        quoted.push(c);
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Double-quoted strings allow control characters and other untrusted
+/// characters to be written as visible escape sequences.
+fn double_quote(s: &str) -> String {
+    let mut quoted = "\"".to_owned();
+    for c in s.chars() {
+        match c {
+            '`' | '$' => {
+                quoted.push('`');
+                quoted.push(c);
+            }
+            c if DOUBLE_QUOTES.contains(&c) => {
+                quoted.push('`');
+                quoted.push(c);
+            }
+            '\0' => quoted.push_str("`0"),
+            '\x07' => quoted.push_str("`a"),
+            '\x08' => quoted.push_str("`b"),
+            '\x0c' => quoted.push_str("`f"),
+            '\n' => quoted.push_str("`n"),
+            '\r' => quoted.push_str("`r"),
+            '\t' => quoted.push_str("`t"),
+            '\x0b' => quoted.push_str("`v"),
+            '\x1b' => quoted.push_str("`e"),
+            c if untrusted_character_kind(c).is_some() => {
+                quoted.push_str(&format!("`u{{{:x}}}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes the program or an arg for PowerShell. A quoted program name is
+/// preceded by the call operator (`&`), since a quoted string at the start of a
+/// line would otherwise be treated as a value rather than a command.
+pub(crate) fn escape_for_powershell(
+    s: &str,
+    is_main_command: bool,
+    sanitize: bool,
+    force_quotes: bool,
+) -> String {
+    if !force_quotes && !needs_escape(s, sanitize) {
+        return s.to_owned();
+    }
+    let quoted =
+        if CONTROL_CHARACTER_REGEX.is_match(s) || (sanitize && contains_untrusted_characters(s)) {
+            double_quote(s)
+        } else {
+            single_quote(s)
+        };
+    if is_main_command {
+        format!("& {}", quoted)
+    } else {
+        quoted
+    }
+}
+
+/// Formats a comment as an inline `<# … #>` block comment.
+pub(crate) fn powershell_block_comment(comment: &str, sanitize: bool) -> String {
+    let comment = if sanitize {
+        make_untrusted_characters_visible(comment)
+    } else {
+        comment.to_owned()
+    };
+    format!(
+        "<# {} #>",
+        comment.replace("#>", "# >").replace(['\n', '\r'], " ")
+    )
+}
@@ -1,8 +1,23 @@
+use std::{
+    env,
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
 use itertools::Itertools;
 
 use crate::{
-    format::{conditional_escape, unconditional_escape, ConditionalEscapeOptions},
-    ArgumentLineWrapping, FormattingOptions,
+    format::{
+        check_env_var_name, comment_substitution, conditional_escape, html_escape, sh_cd_statement,
+        unconditional_escape, wrap_html_code_block, EscapeOptions,
+    },
+    hyperlinks::{file_url_for_arg, osc_8_hyperlink},
+    path_abbreviation::abbreviate_path,
+    powershell::powershell_block_comment,
+    windows::escape_cmd_metacharacters,
+    wrappers::double_quote_nested_command,
+    ArgumentLineWrapping, FormattingOptions, PathAbbreviation, Quoting, ShellDialect,
 };
 
 const DEFAULT_MAIN_INDENTATION: &str = "";
@@ -10,13 +25,30 @@ const DEFAULT_ARG_INDENTATION: &str = "  ";
 
 const INLINE_SEPARATOR: &str = " ";
 const LINE_WRAP_LINE_END: &str = " \\\n";
+const POWERSHELL_LINE_WRAP_LINE_END: &str = " `\n";
+const CMD_LINE_WRAP_LINE_END: &str = " ^\n";
+
+const ANSI_BOLD: &str = "\x1b[1m";
+const ANSI_DIM: &str = "\x1b[2m";
+const ANSI_CYAN: &str = "\x1b[36m";
+const ANSI_RESET: &str = "\x1b[0m";
+
+fn line_wrap_line_end(dialect: ShellDialect) -> &'static str {
+    match dialect {
+        ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh | ShellDialect::Fish => {
+            LINE_WRAP_LINE_END
+        }
+        ShellDialect::PowerShell => POWERSHELL_LINE_WRAP_LINE_END,
+        ShellDialect::Cmd => CMD_LINE_WRAP_LINE_END,
+    }
+}
 
 struct CachedFormattingInfo {
     formatting_options: FormattingOptions,
 
     // TODO: construct lazily for perf?
     main_indentation: String,
-    // arg_indentation: String,
+    arg_indentation: String,
     // line_wrap_separator: String,
     arg_tuple_separator: String,
     post_command_separator: String,
@@ -33,7 +65,8 @@ impl CachedFormattingInfo {
             .arg_indentation
             .clone()
             .unwrap_or(DEFAULT_ARG_INDENTATION.to_owned());
-        let line_wrap_separator = format!("{}{}", LINE_WRAP_LINE_END, arg_indentation);
+        let line_wrap_line_end = line_wrap_line_end(formatting_options.dialect.unwrap_or_default());
+        let line_wrap_separator = format!("{}{}", line_wrap_line_end, arg_indentation);
         let arg_tuple_separator = match formatting_options
             .argument_line_wrapping
             .unwrap_or_default()
@@ -54,7 +87,7 @@ impl CachedFormattingInfo {
             | ArgumentLineWrapping::ByArgument => {
                 format!(
                     "{}{}{}",
-                    LINE_WRAP_LINE_END, main_indentation, arg_indentation
+                    line_wrap_line_end, main_indentation, arg_indentation
                 )
             }
             ArgumentLineWrapping::Inline => INLINE_SEPARATOR.to_owned(),
@@ -70,7 +103,7 @@ impl CachedFormattingInfo {
         Self {
             formatting_options,
             main_indentation,
-            // arg_indentation,
+            arg_indentation,
             // line_wrap_separator,
             arg_tuple_separator,
             entry_separator,
@@ -83,60 +116,416 @@ impl CachedFormattingInfo {
     }
 
     fn escape_arglike(&self, arglike: &str, is_main_command: bool) -> String {
+        let options = EscapeOptions {
+            is_main_command,
+            dialect: self.dialect(),
+            sanitize: self.sanitize(),
+        };
         match self.formatting_options.quoting.unwrap_or_default() {
-            crate::Quoting::Auto => {
-                conditional_escape(arglike, ConditionalEscapeOptions { is_main_command })
-            }
-            crate::Quoting::ExtraSafe => unconditional_escape(arglike),
+            crate::Quoting::Auto => conditional_escape(arglike, options),
+            crate::Quoting::ExtraSafe => unconditional_escape(arglike, options),
+        }
+    }
+
+    fn dialect(&self) -> ShellDialect {
+        self.formatting_options.dialect.unwrap_or_default()
+    }
+
+    fn sanitize(&self) -> bool {
+        self.formatting_options
+            .sanitize_untrusted_characters
+            .unwrap_or(false)
+    }
+
+    fn color(&self) -> bool {
+        self.formatting_options.color.unwrap_or(false)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum TokenRole {
+    Program,
+    Flag,
+    Value,
+    Comment,
+}
+
+impl TokenRole {
+    fn html_class(&self) -> &'static str {
+        match self {
+            TokenRole::Program => "program",
+            TokenRole::Flag => "flag",
+            TokenRole::Value => "value",
+            TokenRole::Comment => "comment",
+        }
+    }
+
+    /// The ANSI escape code used to style the token when `color` is set.
+    fn ansi_style(&self) -> Option<&'static str> {
+        match self {
+            TokenRole::Program => Some(ANSI_BOLD),
+            TokenRole::Flag => Some(ANSI_CYAN),
+            TokenRole::Value => None,
+            TokenRole::Comment => Some(ANSI_DIM),
         }
     }
 }
 
+/// A single word of the printed output, already escaped for the shell.
+struct Token {
+    role: TokenRole,
+    quoted: bool,
+    text: String,
+    hyperlink: Option<String>,
+}
+
+struct Entry {
+    args: Vec<Token>,
+    comment: Option<Token>,
+}
+
 pub(crate) struct PrintBuilder {
-    program_entry: String,
-    serialized_entries: Vec<String>,
+    program_token: Token,
+    entries: Vec<Entry>,
     cached_formatting_info: CachedFormattingInfo,
+    working_directory: Option<PathBuf>,
+    home_directory: Option<String>,
+    /// A `cd …` statement printed before the program.
+    current_dir_prefix: Option<String>,
 }
 
 impl PrintBuilder {
     pub fn new(program_name: &str, formatting_options: FormattingOptions) -> Self {
         let cached_formatting_info = CachedFormattingInfo::new(formatting_options);
-        let program_entry = format!(
-            "{}{}",
-            cached_formatting_info.main_indentation,
+        let program_token = Self::token(
+            TokenRole::Program,
+            program_name,
             cached_formatting_info.format_program_name(program_name),
         );
         Self {
-            program_entry,
-            serialized_entries: vec![],
+            program_token,
+            entries: vec![],
             cached_formatting_info,
+            working_directory: None,
+            home_directory: env::var("HOME").ok(),
+            current_dir_prefix: None,
         }
     }
 
+    /// Prints the command as `cd dir && …`, e.g. so that it can be pasted into
+    /// a shell in any directory.
+    pub fn set_current_dir_prefix(&mut self, dir: &str) {
+        self.current_dir_prefix = Some(self.cd_statement(dir));
+    }
+
+    /// The working directory of the command, used for hyperlinks and path
+    /// abbreviation. Must be called before adding any args.
+    pub fn set_working_directory(&mut self, working_directory: Option<&Path>) {
+        self.working_directory = working_directory.map(Path::to_owned);
+    }
+
+    /// Abbreviates paths as `~/…` using the `HOME` env var that was set
+    /// (`Some`) or removed (`None`) for the command, instead of the `HOME` of
+    /// the current process. (This matches how `~` expands when the command is
+    /// printed after its env vars, e.g. in a `ShellScript`.) Must be called
+    /// before adding any args.
+    pub fn set_home_override(&mut self, home: Option<&OsStr>) {
+        self.home_directory = home.and_then(OsStr::to_str).map(str::to_owned);
+    }
+
+    fn token(role: TokenRole, raw: &str, text: String) -> Token {
+        Token {
+            role,
+            quoted: text != raw,
+            text,
+            hyperlink: None,
+        }
+    }
+
+    fn arg_token(&self, arg: &str) -> Token {
+        let role = if arg.starts_with('-') && arg != "-" {
+            TokenRole::Flag
+        } else {
+            TokenRole::Value
+        };
+        let path_abbreviation = match self.cached_formatting_info.dialect() {
+            ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh | ShellDialect::Fish => {
+                self.cached_formatting_info
+                    .formatting_options
+                    .path_abbreviation
+                    .unwrap_or_default()
+            }
+            ShellDialect::PowerShell | ShellDialect::Cmd => PathAbbreviation::None,
+        };
+        let mut token = match abbreviate_path(
+            arg,
+            path_abbreviation,
+            self.home_directory.as_deref(),
+            self.working_directory.as_deref(),
+        ) {
+            Some((prefix, "")) => Self::token(role, prefix, prefix.to_owned()),
+            Some((prefix, rest)) => {
+                let escaped_rest = self.cached_formatting_info.escape_arglike(rest, false);
+                Token {
+                    role,
+                    quoted: escaped_rest != rest,
+                    text: format!("{}{}", prefix, escaped_rest),
+                    hyperlink: None,
+                }
+            }
+            None => Self::token(
+                role,
+                arg,
+                self.cached_formatting_info.escape_arglike(arg, false),
+            ),
+        };
+        if self
+            .cached_formatting_info
+            .formatting_options
+            .hyperlink_paths
+            .unwrap_or(false)
+        {
+            token.hyperlink = file_url_for_arg(arg, self.working_directory.as_deref());
+        }
+        token
+    }
+
     pub fn add_single_arg(&mut self, arg: &str) {
-        self.serialized_entries
-            .push(self.cached_formatting_info.escape_arglike(arg, false));
+        self.entries.push(Entry {
+            args: vec![self.arg_token(arg)],
+            comment: None,
+        });
+    }
+
+    /// Like `add_arg_group(…)`, for args that may be shell commands themselves
+    /// (e.g. the script of `sh -c`). With `Quoting::Auto`, these are
+    /// double-quoted where that is more readable.
+    pub fn add_nested_command_arg_group<T: AsRef<str>>(&mut self, args: impl Iterator<Item = T>) {
+        self.entries.push(Entry {
+            args: args
+                .map(|arg| self.nested_command_token(arg.as_ref()))
+                .collect(),
+            comment: None,
+        });
+    }
+
+    fn nested_command_token(&self, arg: &str) -> Token {
+        let info = &self.cached_formatting_info;
+        if info.formatting_options.quoting.unwrap_or_default() == Quoting::Auto {
+            if let Some(text) = double_quote_nested_command(arg, info.dialect(), info.sanitize()) {
+                return Self::token(TokenRole::Value, arg, text);
+            }
+        }
+        self.arg_token(arg)
     }
 
     pub fn add_arg_group<T: AsRef<str>>(&mut self, args: impl Iterator<Item = T>) {
-        self.serialized_entries.push(
-            args.map(|arg| {
-                self.cached_formatting_info
-                    .escape_arglike(arg.as_ref(), false)
-            })
-            .join(&self.cached_formatting_info.arg_tuple_separator),
-        );
+        self.entries.push(Entry {
+            args: args.map(|arg| self.arg_token(arg.as_ref())).collect(),
+            comment: None,
+        });
     }
 
-    pub fn get(&self) -> String {
-        let mut output = self.program_entry.clone();
-        if !self.serialized_entries.is_empty() {
-            output = output
-                + &self.cached_formatting_info.post_command_separator
-                + &self
-                    .serialized_entries
-                    .join(&self.cached_formatting_info.entry_separator);
+    /// Appends a comment to the most recently added entry, using a `` `# …` ``
+    /// command substitution so that the output remains valid when pasted.
+    ///
+    /// PowerShell uses an inline `<# … #>` comment instead. Comments are
+    /// omitted for fish and `cmd.exe`, which have no inline comments, and for
+    /// zsh, which runs `#` as a command in interactive shells unless
+    /// `INTERACTIVE_COMMENTS` is set.
+    pub fn add_comment_to_last_entry(&mut self, comment: &str) {
+        let sanitize = self.cached_formatting_info.sanitize();
+        let text = match self.cached_formatting_info.dialect() {
+            ShellDialect::Posix | ShellDialect::Bash => comment_substitution(comment, sanitize),
+            ShellDialect::PowerShell => powershell_block_comment(comment, sanitize),
+            ShellDialect::Zsh | ShellDialect::Fish | ShellDialect::Cmd => return,
+        };
+        if let Some(entry) = self.entries.last_mut() {
+            entry.comment = Some(Token {
+                role: TokenRole::Comment,
+                quoted: false,
+                text,
+                hyperlink: None,
+            });
+        }
+    }
+
+    /// Renders the output, passing each token and each separator through the
+    /// given functions.
+    fn render(
+        &self,
+        render_token: impl Fn(&Token) -> String,
+        render_separator: impl Fn(&str) -> String,
+    ) -> String {
+        let info = &self.cached_formatting_info;
+        let mut output = render_separator(&info.main_indentation);
+        if let Some(current_dir_prefix) = &self.current_dir_prefix {
+            output += &render_separator(&format!("{} && ", current_dir_prefix));
+        }
+        output += &render_token(&self.program_token);
+        if !self.entries.is_empty() {
+            output += &render_separator(&info.post_command_separator);
+            output += &self
+                .entries
+                .iter()
+                .map(|entry| self.render_entry(entry, &render_token, &render_separator))
+                .join(&render_separator(&info.entry_separator));
         };
         output
     }
+
+    fn render_entry(
+        &self,
+        entry: &Entry,
+        render_token: impl Fn(&Token) -> String,
+        render_separator: impl Fn(&str) -> String,
+    ) -> String {
+        let mut serialized = entry.args.iter().map(&render_token).join(&render_separator(
+            &self.cached_formatting_info.arg_tuple_separator,
+        ));
+        if let Some(comment) = &entry.comment {
+            serialized += &render_separator(INLINE_SEPARATOR);
+            serialized += &render_token(comment);
+        }
+        serialized
+    }
+
+    fn render_text_token(&self, token: &Token) -> String {
+        let mut text = token.text.clone();
+        if self.cached_formatting_info.color() {
+            if let Some(style) = token.role.ansi_style() {
+                text = format!("{}{}{}", style, text, ANSI_RESET);
+            }
+        }
+        match &token.hyperlink {
+            Some(url) => osc_8_hyperlink(&text, url),
+            None => text,
+        }
+    }
+
+    pub fn get(&self) -> String {
+        self.render(|token| self.render_text_token(token), str::to_owned)
+    }
+
+    /// Renders the program and then each entry on a line of its own (with
+    /// indentation, but without line continuations), for callers that lay out
+    /// the lines themselves.
+    pub fn get_lines(&self) -> Vec<String> {
+        let info = &self.cached_formatting_info;
+        let mut lines =
+            vec![info.main_indentation.clone() + &self.render_text_token(&self.program_token)];
+        for entry in &self.entries {
+            lines.push(format!(
+                "{}{}{}",
+                info.main_indentation,
+                info.arg_indentation,
+                self.render_entry(entry, |token| self.render_text_token(token), str::to_owned)
+            ));
+        }
+        lines
+    }
+
+    /// The text that ends a line to continue the command on the next line
+    /// (e.g. ` \`).
+    pub fn line_continuation(&self) -> &'static str {
+        line_wrap_line_end(self.cached_formatting_info.dialect()).trim_end_matches('\n')
+    }
+
+    /// Escapes `s` as an arg (not the program) for the configured dialect.
+    pub fn escape_arg(&self, s: &str) -> String {
+        self.cached_formatting_info.escape_arglike(s, false)
+    }
+
+    /// A statement that changes the working directory to `dir` in the
+    /// configured dialect.
+    pub(crate) fn cd_statement(&self, dir: &str) -> String {
+        match self.cached_formatting_info.dialect() {
+            ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh | ShellDialect::Fish => {
+                sh_cd_statement(dir, &self.escape_arg(dir))
+            }
+            // A quoted arg is never read as a parameter name.
+            ShellDialect::PowerShell if dir.starts_with('-') => format!(
+                "cd {}",
+                unconditional_escape(
+                    dir,
+                    EscapeOptions {
+                        is_main_command: false,
+                        dialect: ShellDialect::PowerShell,
+                        sanitize: self.cached_formatting_info.sanitize(),
+                    }
+                )
+            ),
+            ShellDialect::PowerShell => format!("cd {}", self.escape_arg(dir)),
+            ShellDialect::Cmd => format!("cd /d {}", self.escape_arg(dir)),
+        }
+    }
+
+    /// A statement that sets (`Some`) or removes (`None`) the env var `key`
+    /// in the configured dialect. This returns an error if `key` is not a
+    /// valid shell variable name.
+    pub(crate) fn env_statement(&self, key: &str, value: Option<&str>) -> io::Result<String> {
+        check_env_var_name(key)?;
+        let info = &self.cached_formatting_info;
+        Ok(match (info.dialect(), value) {
+            (ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh, Some(value)) => {
+                format!("export {}={}", key, self.escape_arg(value))
+            }
+            (ShellDialect::Posix | ShellDialect::Bash | ShellDialect::Zsh, None) => {
+                format!("unset {}", key)
+            }
+            (ShellDialect::Fish, Some(value)) => {
+                format!("set -x {} {}", key, self.escape_arg(value))
+            }
+            (ShellDialect::Fish, None) => format!("set -e {}", key),
+            // A bare word would be run as a command, so the value is always
+            // quoted.
+            (ShellDialect::PowerShell, Some(value)) => format!(
+                "$env:{} = {}",
+                key,
+                unconditional_escape(
+                    value,
+                    EscapeOptions {
+                        is_main_command: false,
+                        dialect: ShellDialect::PowerShell,
+                        sanitize: info.sanitize(),
+                    }
+                )
+            ),
+            (ShellDialect::PowerShell, None) => format!("$env:{} = $null", key),
+            // `set` takes the rest of the line literally (after `^` escapes).
+            (ShellDialect::Cmd, Some(value)) => {
+                format!("set {}={}", key, escape_cmd_metacharacters(value))
+            }
+            (ShellDialect::Cmd, None) => format!("set {}=", key),
+        })
+    }
+
+    /// A comment noting that the environment of the current process is not
+    /// inherited (since no statement can express this).
+    pub(crate) fn env_clear_comment(&self) -> String {
+        match self.cached_formatting_info.dialect() {
+            ShellDialect::Cmd => "rem (environment cleared)".to_owned(),
+            _ => "# (environment cleared)".to_owned(),
+        }
+    }
+
+    /// Renders the output as an HTML `<pre><code>` block, with a `<span>` for
+    /// each token.
+    pub fn get_html(&self) -> String {
+        let code = self.render(
+            |token| {
+                let mut classes = token.role.html_class().to_owned();
+                if token.quoted {
+                    classes += " quoted";
+                }
+                format!(
+                    "<span class=\"{}\">{}</span>",
+                    classes,
+                    html_escape(&token.text)
+                )
+            },
+            html_escape,
+        );
+        wrap_html_code_block(&code)
+    }
 }
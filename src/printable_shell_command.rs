@@ -1,6 +1,9 @@
 use std::{
+    env,
     ffi::{OsStr, OsString},
+    io,
     ops::{Deref, DerefMut},
+    path::PathBuf,
     process::Command,
     str::Utf8Error,
 };
@@ -8,15 +11,37 @@ use std::{
 use itertools::Itertools;
 
 use crate::{
-    command::{add_arg_from_command, add_arg_from_command_lossy},
+    arg_grouping::group_args,
+    command::{
+        add_arg_from_command, add_arg_from_command_lossy, copy_env_and_current_dir,
+        set_print_builder_environment,
+    },
     print_builder::PrintBuilder,
     shell_printable::{ShellPrintable, ShellPrintableWithOptions},
-    FormattingOptions,
+    untrusted_characters::find_untrusted_characters,
+    windows::windows_command_line,
+    ArgGrouping, CommandSchema, FormattingOptions, SchemaWarning, UntrustedCharacter,
+    UntrustedCharacterLocation,
 };
 
+#[derive(Clone)]
+struct ArgGroup {
+    args: Vec<OsString>,
+    comment: Option<String>,
+    /// Whether this group was added by `wrap_with_prefix(…)`.
+    is_wrapper_prefix: bool,
+    /// Whether this group was added by `nested_command_args(…)`.
+    is_nested_command: bool,
+}
+
 pub struct PrintableShellCommand {
-    arg_groups: Vec<Vec<OsString>>,
+    arg_groups: Vec<ArgGroup>,
     command: Command,
+    /// Whether `env_clear()` was called (which `Command` does not expose).
+    env_cleared: bool,
+    /// Whether the working directory is printed as a `cd … &&` prefix (for
+    /// commands that are meant to be pasted from anywhere).
+    print_current_dir: bool,
 }
 
 // TODO: this depends on the interface to `Command` supporting the *appending*
@@ -28,6 +53,8 @@ impl PrintableShellCommand {
         Self {
             arg_groups: vec![],
             command: Command::new(program),
+            env_cleared: false,
+            print_current_dir: false,
         }
     }
 
@@ -53,7 +80,12 @@ impl PrintableShellCommand {
     }
 
     fn arg_without_adoption<S: AsRef<OsStr>>(&mut self, arg: S) -> S {
-        self.arg_groups.push(vec![(&arg).into()]);
+        self.arg_groups.push(ArgGroup {
+            args: vec![(&arg).into()],
+            comment: None,
+            is_wrapper_prefix: false,
+            is_nested_command: false,
+        });
         arg
     }
 
@@ -63,12 +95,47 @@ impl PrintableShellCommand {
         S: AsRef<OsStr>,
     {
         self.adopt_args();
-        let args = self.args_without_adoption(args);
+        let args = self.args_without_adoption(args, None);
+        self.command.args(args);
+        self
+    }
+
+    /// Add an arg group (like `.args(…)`) that is printed with an explanatory
+    /// comment. The comment is rendered using a `` `# …` `` command
+    /// substitution, which expands to nothing when the printed command is
+    /// pasted into a shell. If `args` is empty, nothing is printed (not even
+    /// the comment).
+    pub fn args_with_comment<I, S>(&mut self, args: I, comment: &str) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.adopt_args();
+        let args = self.args_without_adoption(args, Some(comment.to_owned()));
+        self.command.args(args);
+        self
+    }
+
+    /// Adds an arg group (like `.args(…)`) whose args may be shell commands
+    /// themselves (e.g. the script of `sh -c`), which are printed in double
+    /// quotes where that is more readable.
+    pub(crate) fn nested_command_args<I, S>(&mut self, args: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.adopt_args();
+        let args = self.args_without_adoption(args, None);
+        if !args.is_empty() {
+            if let Some(arg_group) = self.arg_groups.last_mut() {
+                arg_group.is_nested_command = true;
+            }
+        }
         self.command.args(args);
         self
     }
 
-    fn args_without_adoption<I, S>(&mut self, args: I) -> Vec<OsString>
+    fn args_without_adoption<I, S>(&mut self, args: I, comment: Option<String>) -> Vec<OsString>
     where
         I: IntoIterator<Item = S>,
         S: AsRef<OsStr>,
@@ -77,7 +144,15 @@ impl PrintableShellCommand {
             .into_iter()
             .map(|arg| std::convert::Into::<OsString>::into(&arg))
             .collect();
-        self.arg_groups.push(args.clone());
+        // An empty group would be printed as an empty line.
+        if !args.is_empty() {
+            self.arg_groups.push(ArgGroup {
+                args: args.clone(),
+                comment,
+                is_wrapper_prefix: false,
+                is_nested_command: false,
+            });
+        }
         args
     }
 
@@ -86,7 +161,7 @@ impl PrintableShellCommand {
         for either_or_both in self
             .arg_groups
             .iter()
-            .flatten()
+            .flat_map(|arg_group| &arg_group.args)
             .zip_longest(self.command.get_args())
         {
             match either_or_both {
@@ -117,6 +192,149 @@ impl PrintableShellCommand {
         self
     }
 
+    /// The command line that a Windows program would receive for this command
+    /// (e.g. via `CreateProcess`), quoted according to the rules that
+    /// `CommandLineToArgvW` and the MSVC runtime use to split it back into
+    /// args.
+    ///
+    /// To print a command that can be pasted into `cmd.exe`, use
+    /// [`crate::ShellDialect::Cmd`] instead.
+    pub fn windows_command_line(&self) -> Result<String, Utf8Error> {
+        let program = TryInto::<&str>::try_into(self.get_program())?;
+        let args = self
+            .get_args()
+            .map(TryInto::<&str>::try_into)
+            .collect::<Result<Vec<&str>, Utf8Error>>()?;
+        Ok(windows_command_line(program, args.into_iter()))
+    }
+
+    // Calls `.to_string_lossy()` on the program name and args.
+    pub fn windows_command_line_lossy(&self) -> String {
+        let args: Vec<String> = self
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        windows_command_line(
+            &self.get_program().to_string_lossy(),
+            args.iter().map(String::as_str),
+        )
+    }
+
+    /// Finds characters in the program name or args that could make the
+    /// printed command misleading when displayed in a terminal (see
+    /// [`FormattingOptions::sanitize_untrusted_characters`]).
+    pub fn untrusted_characters(&self) -> Vec<UntrustedCharacter> {
+        let mut untrusted_characters: Vec<UntrustedCharacter> = find_untrusted_characters(
+            &self.get_program().to_string_lossy(),
+            UntrustedCharacterLocation::Program,
+        )
+        .collect();
+        for (i, arg) in self.get_args().enumerate() {
+            untrusted_characters.extend(find_untrusted_characters(
+                &arg.to_string_lossy(),
+                UntrustedCharacterLocation::Arg(i),
+            ));
+        }
+        untrusted_characters
+    }
+
+    /// The args of the last arg group (without adopting args first).
+    pub(crate) fn trailing_arg_group(&self) -> &[OsString] {
+        self.arg_groups
+            .last()
+            .map(|arg_group| arg_group.args.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The args of each arg group, followed by any args that have not been
+    /// adopted yet (each as its own group).
+    pub(crate) fn arg_group_args(&self) -> Vec<Vec<OsString>> {
+        self.arg_groups
+            .iter()
+            .map(|arg_group| arg_group.args.clone())
+            .chain(self.args_to_adopt().into_iter().map(|arg| vec![arg]))
+            .collect()
+    }
+
+    /// A copy of this command (including its working directory and env vars),
+    /// with the args of the last arg group replaced by `trailing_args`.
+    pub(crate) fn with_trailing_arg_group(&self, trailing_args: Vec<OsString>) -> Self {
+        let mut arg_groups = self.arg_groups.clone();
+        if let Some(last_arg_group) = arg_groups.last_mut() {
+            last_arg_group.args = trailing_args;
+        }
+        let mut command = Command::new(self.get_program());
+        if self.env_cleared {
+            command.env_clear();
+        }
+        copy_env_and_current_dir(&self.command, &mut command);
+        for arg_group in &arg_groups {
+            command.args(&arg_group.args);
+        }
+        Self {
+            arg_groups,
+            command,
+            env_cleared: self.env_cleared,
+            print_current_dir: self.print_current_dir,
+        }
+    }
+
+    /// Replaces the program with `program`. The original program is added to
+    /// the end of the last of `prefix_arg_groups`, which are inserted before
+    /// the original arg groups. If the command was already wrapped, the first
+    /// group of the previous prefix is merged into the last new group, so that
+    /// the previous wrapper's program is always printed together with the
+    /// start of its args.
+    ///
+    /// The working directory and env vars are kept only if
+    /// `keep_env_and_current_dir` is set (otherwise the caller is expected to
+    /// pass them to `program` some other way).
+    pub(crate) fn wrap_with_prefix(
+        &mut self,
+        program: &OsStr,
+        mut prefix_arg_groups: Vec<Vec<OsString>>,
+        keep_env_and_current_dir: bool,
+    ) {
+        self.adopt_args();
+        let mut command = Command::new(program);
+        if keep_env_and_current_dir {
+            if self.env_cleared {
+                command.env_clear();
+            }
+            copy_env_and_current_dir(&self.command, &mut command);
+        } else {
+            self.env_cleared = false;
+        }
+        let mut last_prefix_arg_group = prefix_arg_groups.pop().unwrap_or_default();
+        last_prefix_arg_group.push(self.get_program().to_owned());
+        if let Some(first_arg_group) = self.arg_groups.first() {
+            if first_arg_group.is_wrapper_prefix {
+                last_prefix_arg_group.extend(self.arg_groups.remove(0).args);
+            }
+        }
+        let mut arg_groups: Vec<ArgGroup> = prefix_arg_groups
+            .into_iter()
+            .map(|args| ArgGroup {
+                args,
+                comment: None,
+                is_wrapper_prefix: true,
+                is_nested_command: false,
+            })
+            .collect();
+        arg_groups.push(ArgGroup {
+            args: last_prefix_arg_group,
+            comment: None,
+            is_wrapper_prefix: true,
+            is_nested_command: false,
+        });
+        arg_groups.append(&mut self.arg_groups);
+        for arg_group in &arg_groups {
+            command.args(&arg_group.args);
+        }
+        self.command = command;
+        self.arg_groups = arg_groups;
+    }
+
     fn add_unadopted_args_lossy(&self, print_builder: &mut PrintBuilder) {
         for arg in self.args_to_adopt() {
             add_arg_from_command_lossy(print_builder, arg.as_os_str());
@@ -131,6 +349,41 @@ impl PrintableShellCommand {
     }
 }
 
+impl PrintableShellCommand {
+    /// Like `Command::env_clear()`, but also tracked so that it is taken into
+    /// account by `fingerprint()` and `diff(…)`. (Calling `env_clear()` on the
+    /// underlying `Command` directly is not tracked, and neither is an
+    /// adopted `Command` that had its environment cleared.)
+    pub fn env_clear(&mut self) -> &mut Self {
+        self.command.env_clear();
+        self.env_cleared = true;
+        self
+    }
+
+    /// Whether `env_clear()` was called, i.e. the command does not inherit the
+    /// environment of the current process.
+    pub fn is_env_cleared(&self) -> bool {
+        self.env_cleared
+    }
+
+    /// A copy of this command, including its working directory and env vars
+    /// (but not other configuration, which `Command` does not expose).
+    pub(crate) fn duplicate(&self) -> Self {
+        let mut command = Command::new(self.get_program());
+        if self.env_cleared {
+            command.env_clear();
+        }
+        copy_env_and_current_dir(&self.command, &mut command);
+        command.args(self.command.get_args());
+        Self {
+            arg_groups: self.arg_groups.clone(),
+            command,
+            env_cleared: self.env_cleared,
+            print_current_dir: self.print_current_dir,
+        }
+    }
+}
+
 impl Deref for PrintableShellCommand {
     type Target = Command;
 
@@ -152,58 +405,192 @@ impl From<Command> for PrintableShellCommand {
         let mut printable_shell_command = Self {
             arg_groups: vec![],
             command,
+            env_cleared: false,
+            print_current_dir: false,
         };
         printable_shell_command.adopt_args();
         printable_shell_command
     }
 }
 
-impl ShellPrintableWithOptions for PrintableShellCommand {
-    fn printable_invocation_string_lossy_with_options(
-        &self,
-        formatting_options: FormattingOptions,
-    ) -> String {
+impl PrintableShellCommand {
+    /// Adopts a `Command`, grouping its args using
+    /// [`ArgGrouping::Schema`] (e.g. a flag is printed on the same line as its
+    /// value).
+    pub fn from_command_with_inferred_groups(command: Command) -> Self {
+        let arg_groups = group_args(
+            command.get_program(),
+            &command.get_args().collect::<Vec<_>>(),
+            ArgGrouping::Schema,
+        )
+        .into_iter()
+        .map(|args| ArgGroup {
+            args: args.into_iter().map(OsStr::to_owned).collect(),
+            comment: None,
+            is_wrapper_prefix: false,
+            is_nested_command: false,
+        })
+        .collect();
+        Self {
+            arg_groups,
+            command,
+            env_cleared: false,
+            print_current_dir: false,
+        }
+    }
+}
+
+impl PrintableShellCommand {
+    /// The invocation of the current process (from `std::env::args_os()`),
+    /// e.g. to print a hint like "re-run with: …" after adding an arg. Args are
+    /// grouped using [`ArgGrouping::Schema`].
+    pub fn current_process() -> Self {
+        let mut args_os = env::args_os();
+        let program = args_os
+            .next()
+            .or_else(|| env::current_exe().ok().map(PathBuf::into_os_string))
+            .unwrap_or_default();
+        let mut command = Command::new(program);
+        command.args(args_os);
+        Self::from_command_with_inferred_groups(command)
+    }
+
+    /// Like `current_process()`, but also sets the working directory to the
+    /// current directory, and prefixes the command with `env KEY=value …` for
+    /// each of `env_var_names` that is set in the current process (see
+    /// `wrap_with_env(…)`). The working directory is printed as a `cd … &&`
+    /// prefix, so that the printed command can be pasted from anywhere.
+    pub fn current_process_with_env_and_current_dir<I, K>(env_var_names: I) -> io::Result<Self>
+    where
+        I: IntoIterator<Item = K>,
+        K: AsRef<OsStr>,
+    {
+        let mut printable_shell_command = Self::current_process();
+        printable_shell_command.current_dir(env::current_dir()?);
+        printable_shell_command.print_current_dir = true;
+        let vars: Vec<(OsString, OsString)> = env_var_names
+            .into_iter()
+            .filter_map(|key| {
+                let value = env::var_os(&key)?;
+                Some((key.as_ref().to_owned(), value))
+            })
+            .collect();
+        if !vars.is_empty() {
+            printable_shell_command.wrap_with_env(vars)?;
+        }
+        Ok(printable_shell_command)
+    }
+
+    /// Checks the args against the [`CommandSchema`] for the program (if there
+    /// is one), e.g. for flags that are missing their value.
+    pub fn schema_warnings(&self) -> Vec<SchemaWarning> {
+        match CommandSchema::for_program(self.get_program()) {
+            Some(schema) => schema.validate(&self.get_args().collect::<Vec<_>>()),
+            None => vec![],
+        }
+    }
+}
+
+impl PrintableShellCommand {
+    fn print_builder_lossy(&self, formatting_options: FormattingOptions) -> PrintBuilder {
         let mut print_builder =
             PrintBuilder::new(&self.get_program().to_string_lossy(), formatting_options);
+        set_print_builder_environment(&mut print_builder, &self.command);
+        if self.print_current_dir {
+            if let Some(current_dir) = self.get_current_dir() {
+                print_builder.set_current_dir_prefix(&current_dir.to_string_lossy());
+            }
+        }
         for arg_group in &self.arg_groups {
             let mut strings: Vec<String> = vec![];
-            for arg in arg_group {
+            for arg in &arg_group.args {
                 strings.push(arg.to_string_lossy().to_string())
             }
-            print_builder.add_arg_group(strings.iter());
+            if arg_group.is_nested_command {
+                print_builder.add_nested_command_arg_group(strings.iter());
+            } else {
+                print_builder.add_arg_group(strings.iter());
+            }
+            if let Some(comment) = &arg_group.comment {
+                print_builder.add_comment_to_last_entry(comment);
+            }
         }
         self.add_unadopted_args_lossy(&mut print_builder);
-        print_builder.get()
+        print_builder
     }
 
-    fn printable_invocation_string_with_options(
+    fn print_builder(
         &self,
         formatting_options: FormattingOptions,
-    ) -> Result<String, Utf8Error> {
+    ) -> Result<PrintBuilder, Utf8Error> {
         let mut print_builder = PrintBuilder::new(
             TryInto::<&str>::try_into(self.get_program())?,
             formatting_options,
         );
+        set_print_builder_environment(&mut print_builder, &self.command);
+        if self.print_current_dir {
+            if let Some(current_dir) = self.get_current_dir() {
+                print_builder
+                    .set_current_dir_prefix(TryInto::<&str>::try_into(current_dir.as_os_str())?);
+            }
+        }
         for arg_group in &self.arg_groups {
             let mut strings: Vec<&str> = vec![];
-            for arg in arg_group {
+            for arg in &arg_group.args {
                 let s = TryInto::<&str>::try_into(arg.as_os_str())?;
                 strings.push(s)
             }
-            print_builder.add_arg_group(strings.into_iter());
+            if arg_group.is_nested_command {
+                print_builder.add_nested_command_arg_group(strings.into_iter());
+            } else {
+                print_builder.add_arg_group(strings.into_iter());
+            }
+            if let Some(comment) = &arg_group.comment {
+                print_builder.add_comment_to_last_entry(comment);
+            }
         }
         self.add_unadopted_args(&mut print_builder)?;
-        Ok(print_builder.get())
+        Ok(print_builder)
+    }
+}
+
+impl ShellPrintableWithOptions for PrintableShellCommand {
+    fn printable_invocation_string_lossy_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> String {
+        self.print_builder_lossy(formatting_options).get()
+    }
+
+    fn printable_invocation_string_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> Result<String, Utf8Error> {
+        Ok(self.print_builder(formatting_options)?.get())
+    }
+
+    fn printable_invocation_html_lossy_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> String {
+        self.print_builder_lossy(formatting_options).get_html()
+    }
+
+    fn printable_invocation_html_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> Result<String, Utf8Error> {
+        Ok(self.print_builder(formatting_options)?.get_html())
     }
 }
 
 impl ShellPrintable for PrintableShellCommand {
     fn printable_invocation_string(&self) -> Result<String, Utf8Error> {
-        self.printable_invocation_string_with_options(Default::default())
+        self.printable_invocation_string_with_options(FormattingOptions::process_default())
     }
 
     fn printable_invocation_string_lossy(&self) -> String {
-        self.printable_invocation_string_lossy_with_options(Default::default())
+        self.printable_invocation_string_lossy_with_options(FormattingOptions::process_default())
     }
 }
 
@@ -213,7 +600,7 @@ mod tests {
 
     use crate::{
         FormattingOptions, PrintableShellCommand, Quoting, ShellPrintable,
-        ShellPrintableWithOptions,
+        ShellPrintableWithOptions, UntrustedCharacter, UntrustedCharacterLocation,
     };
 
     #[test]
@@ -553,4 +940,421 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn presets() -> Result<(), Utf8Error> {
+        let printable_shell_command = rsync_command_for_testing();
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_string_with_options(FormattingOptions::compact())?,
+            "rsync -avz --exclude .DS_Store --exclude .git ./dist/web/experiments.cubing.net/test/deploy/ experiments.cubing.net:~/experiments.cubing.net/test/deploy/"
+        );
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_string_with_options(FormattingOptions::ci_log())?,
+            "rsync -avz \\
+  --exclude .DS_Store \\
+  --exclude .git \\
+  ./dist/web/experiments.cubing.net/test/deploy/ \\
+  experiments.cubing.net:~/experiments.cubing.net/test/deploy/"
+        );
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_string_with_options(FormattingOptions::documentation())?,
+            printable_shell_command.printable_invocation_string()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn markdown() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.args_with_comment(["hello", "world"], "greeting");
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_markdown_with_options(Default::default())?,
+            "```shell
+echo \\
+  hello world `# greeting`
+```"
+        );
+
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.arg("```");
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_markdown_lossy_with_options(FormattingOptions::compact()),
+            "````shell
+echo '```'
+````"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn html() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("grep");
+        printable_shell_command
+            .args_with_comment(["-e", "<a href=\"#\">"], "links")
+            .arg("index.html");
+        assert_eq!(
+            printable_shell_command.printable_invocation_html_with_options(Default::default())?,
+            "<pre><code class=\"language-shell\"><span class=\"program\">grep</span> \\
+  <span class=\"flag\">-e</span> <span class=\"value quoted\">&#39;&lt;a href=&quot;#&quot;&gt;&#39;</span> <span class=\"comment\">`# links`</span> \\
+  <span class=\"value\">index.html</span></code></pre>"
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_html_with_options(Default::default())?,
+            printable_shell_command
+                .printable_invocation_html_lossy_with_options(Default::default())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hyperlink_paths() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("cat");
+        printable_shell_command.args(["-n", "Cargo.toml"]);
+        printable_shell_command.current_dir(env!("CARGO_MANIFEST_DIR"));
+        let printed = printable_shell_command.printable_invocation_string_with_options(
+            FormattingOptions::new().hyperlink_paths(true),
+        )?;
+        assert!(printed.starts_with("cat \\\n  -n \x1b]8;;file://"));
+        assert!(printed.ends_with("/Cargo.toml\x1b\\Cargo.toml\x1b]8;;\x1b\\"));
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            "cat \\
+  -n Cargo.toml"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn path_abbreviation() -> Result<(), Utf8Error> {
+        let home = "/home/me";
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command
+            .arg(format!("{}/My Documents/", home))
+            .arg(home)
+            .arg(format!("{}/src/main.rs", home))
+            .arg("/etc/hosts");
+        printable_shell_command
+            .current_dir(format!("{}/src", home))
+            .env("HOME", home);
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().path_abbreviation(crate::PathAbbreviation::Home)
+            )?,
+            "ls \\
+  ~/'My Documents/' \\
+  ~ \\
+  ~/src/main.rs \\
+  /etc/hosts"
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new()
+                    .path_abbreviation(crate::PathAbbreviation::HomeAndWorkingDirectory)
+                    .quoting(Quoting::ExtraSafe)
+            )?,
+            "'ls' \\
+  ~/'My Documents/' \\
+  ~ \\
+  ./'main.rs' \\
+  '/etc/hosts'"
+        );
+
+        // Without a home directory, `~` cannot be used.
+        printable_shell_command.env_remove("HOME");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::compact().path_abbreviation(crate::PathAbbreviation::Home)
+            )?,
+            "ls '/home/me/My Documents/' /home/me /home/me/src/main.rs /etc/hosts"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn color() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.args_with_comment(["-l", "src"], "long");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::compact().color(true),
+            )?,
+            "\x1b[1mls\x1b[0m \x1b[36m-l\x1b[0m src \x1b[2m`# long`\x1b[0m"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dialects() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.args(["hi!there", "~", "it's\nme"]);
+        // `Posix` is the default.
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            "echo \\
+  hi!there '~' 'it'\\''s
+me'"
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().dialect(crate::ShellDialect::Bash)
+            )?,
+            "echo \\
+  'hi!there' '~' $'it\\'s\\nme'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sanitize_untrusted_characters() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("cat");
+        printable_shell_command
+            .args_with_comment(["report\u{202e}fdp.exe"], "\x1b[2Jcleared")
+            .arg("notes.txt");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().sanitize_untrusted_characters(true)
+            )?,
+            "cat \\
+  $'report\\xe2\\x80\\xaefdp.exe' `# \\\\u{1b}[2Jcleared` \\
+  notes.txt"
+        );
+        assert_eq!(
+            printable_shell_command.untrusted_characters(),
+            vec![UntrustedCharacter {
+                location: UntrustedCharacterLocation::Arg(0),
+                character: '\u{202e}',
+                kind: crate::UntrustedCharacterKind::Bidi,
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn windows() -> Result<(), Utf8Error> {
+        let mut printable_shell_command =
+            PrintableShellCommand::new(r"C:\Program Files\WiX\wix.exe");
+        printable_shell_command
+            .arg("build")
+            .args(["-d", r#"ProductName="My App" & Co"#])
+            .args_with_comment(["-o", r"C:\out dir\"], "output")
+            .arg("installer.wxs");
+        assert_eq!(
+            printable_shell_command.windows_command_line()?,
+            r#""C:\Program Files\WiX\wix.exe" build -d "ProductName=\"My App\" & Co" -o "C:\out dir\\" installer.wxs"#
+        );
+        assert_eq!(
+            printable_shell_command.windows_command_line()?,
+            printable_shell_command.windows_command_line_lossy()
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().dialect(crate::ShellDialect::Cmd)
+            )?,
+            r#"^"C:\Program Files\WiX\wix.exe^" ^
+  build ^
+  -d ^"ProductName=\^"My App\^" ^& Co^" ^
+  -o ^"C:\out dir\\^" ^
+  installer.wxs"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn powershell() -> Result<(), Utf8Error> {
+        let mut printable_shell_command =
+            PrintableShellCommand::new(r"C:\Program Files\ffmpeg\ffmpeg.exe");
+        printable_shell_command
+            .args_with_comment(["-i", "My video.mp4"], "input #> file")
+            .args(["-filter:v", "setpts=2.0*PTS"])
+            .arg("out.mov");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().dialect(crate::ShellDialect::PowerShell)
+            )?,
+            r"& 'C:\Program Files\ffmpeg\ffmpeg.exe' `
+  -i 'My video.mp4' <# input # > file #> `
+  '-filter:v' 'setpts=2.0*PTS' `
+  out.mov"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn snapshots() {
+        let printable_shell_command = rsync_command_for_testing();
+        crate::assert_printable_snapshot!(printable_shell_command, "rsync");
+        crate::assert_printable_snapshot!(
+            printable_shell_command,
+            FormattingOptions::default()
+                .argument_line_wrapping(crate::ArgumentLineWrapping::NestedByEntry)
+                .arg_indentation("\t"),
+            "rsync-nested-by-entry"
+        );
+    }
+
+    #[test]
+    fn from_command_with_inferred_groups() -> Result<(), Utf8Error> {
+        let mut command = Command::new("git");
+        command.args([
+            "-C",
+            "./repo",
+            "log",
+            "--format=%h",
+            "--author",
+            "-me-",
+            "-n",
+            "5",
+            "--",
+            "-weird-file",
+        ]);
+        let mut printable_shell_command =
+            PrintableShellCommand::from_command_with_inferred_groups(command);
+        printable_shell_command.arg("README.md");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            "git \\
+  -C ./repo \\
+  log \\
+  --format=%h \\
+  --author -me- \\
+  -n 5 \\
+  -- \\
+  -weird-file \\
+  README.md"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn schema_warnings() {
+        let mut printable_shell_command = PrintableShellCommand::new("curl");
+        printable_shell_command
+            .args(["-o", "--silent"])
+            .arg("https://example.com");
+        assert_eq!(
+            printable_shell_command.schema_warnings(),
+            [crate::SchemaWarning::MissingValue {
+                arg_index: 0,
+                flag: "-o".to_owned()
+            }]
+        );
+        assert_eq!(
+            PrintableShellCommand::new("example")
+                .arg("-o")
+                .schema_warnings(),
+            []
+        );
+    }
+
+    #[test]
+    fn current_process() -> Result<(), std::io::Error> {
+        let args: Vec<_> = std::env::args_os().collect();
+        let printable_shell_command = PrintableShellCommand::current_process();
+        assert_eq!(printable_shell_command.get_program(), args[0]);
+        assert_eq!(
+            printable_shell_command.get_args().collect::<Vec<_>>(),
+            args[1..].iter().collect::<Vec<_>>()
+        );
+
+        let printable_shell_command =
+            PrintableShellCommand::current_process_with_env_and_current_dir([
+                "PATH",
+                "PRINTABLE_SHELL_COMMAND_TEST_UNSET",
+            ])?;
+        assert_eq!(printable_shell_command.get_program(), "env");
+        let wrapped_args: Vec<_> = printable_shell_command.get_args().collect();
+        assert!(wrapped_args[0].to_string_lossy().starts_with("PATH="));
+        assert_eq!(wrapped_args[1], args[0]);
+        assert_eq!(
+            printable_shell_command.get_current_dir(),
+            Some(std::env::current_dir()?.as_path())
+        );
+        let printed = printable_shell_command.printable_invocation_string_lossy();
+        assert!(printed.starts_with("cd "));
+        assert!(printed.contains(" && env \\\n  PATH="));
+        Ok(())
+    }
+
+    #[test]
+    fn print_current_dir() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command
+            .arg("-l")
+            .current_dir("/tmp/My Files");
+        printable_shell_command.print_current_dir = true;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            "cd '/tmp/My Files' && ls \\
+  -l"
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::compact().dialect(crate::ShellDialect::Cmd)
+            )?,
+            "cd /d ^\"/tmp/My Files^\" && ls -l"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn args_with_comment() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("ffmpeg");
+        printable_shell_command
+            .args_with_comment(["-i", "./test/My video.mp4"], "input file")
+            .args_with_comment(["-filter:v", "setpts=2.0*PTS"], "slow down video by 2×")
+            .arg("./test/My video (slow-mo).mov");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            "ffmpeg \\
+  -i './test/My video.mp4' `# input file` \\
+  -filter:v 'setpts=2.0*PTS' `# slow down video by 2×` \\
+  './test/My video (slow-mo).mov'"
+        );
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            printable_shell_command.printable_invocation_string_lossy(),
+        );
+        // Interactive zsh runs `#` as a command by default.
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions::new().dialect(crate::ShellDialect::Zsh)
+            )?,
+            "ffmpeg \\
+  -i './test/My video.mp4' \\
+  -filter:v 'setpts=2.0*PTS' \\
+  './test/My video (slow-mo).mov'"
+        );
+        assert_eq!(
+            printable_shell_command.get_args().collect::<Vec<_>>(),
+            [
+                "-i",
+                "./test/My video.mp4",
+                "-filter:v",
+                "setpts=2.0*PTS",
+                "./test/My video (slow-mo).mov"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn args_with_comment_neutralizes_special_characters() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.args_with_comment(["hi"], "`rm -rf /`\nnewline\\");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string_with_options(
+                FormattingOptions {
+                    argument_line_wrapping: Some(crate::ArgumentLineWrapping::Inline),
+                    ..Default::default()
+                }
+            )?,
+            "echo hi `# 'rm -rf /' newline\\\\`"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,323 @@
+use std::{
+    ffi::{OsStr, OsString},
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Output},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{format::json_string, PrintableShellCommand, ShellScript};
+
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+/// A command that was run while recording (see `Recording::start()`).
+#[derive(Clone, Debug)]
+pub struct RecordedCommand {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    pub current_dir: Option<PathBuf>,
+    /// Env vars set (`Some`) or removed (`None`) for the command, relative to
+    /// the environment of the current process (or to an empty environment, if
+    /// `env_cleared` is set).
+    pub envs: Vec<(OsString, Option<OsString>)>,
+    /// Whether the command was run with `env_clear()`.
+    pub env_cleared: bool,
+    pub start_time: SystemTime,
+    /// `None` for commands that were only spawned (the process may still be
+    /// running).
+    pub duration: Option<Duration>,
+    /// `None` for commands that were only spawned, or that failed to run.
+    pub exit_status: Option<ExitStatus>,
+    /// The error if the command could not be run (e.g. if the program was not
+    /// found).
+    pub error: Option<String>,
+    /// The lines of a `ShellScript` that run this command, rendered when the
+    /// command was run, or the reason they could not be rendered.
+    script_section: Result<String, String>,
+}
+
+impl RecordedCommand {
+    fn new(command: &PrintableShellCommand, start_time: SystemTime) -> Self {
+        Self {
+            program: command.get_program().to_owned(),
+            args: command.get_args().map(OsStr::to_owned).collect(),
+            current_dir: command.get_current_dir().map(Path::to_owned),
+            envs: command
+                .get_envs()
+                .map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned)))
+                .collect(),
+            env_cleared: command.is_env_cleared(),
+            start_time,
+            duration: None,
+            exit_status: None,
+            error: None,
+            script_section: ShellScript::new()
+                .script_section(command)
+                .map_err(|error| error.to_string()),
+        }
+    }
+
+    /// Renders the command as a single JSON object (without a trailing
+    /// newline). Non-UTF-8 values are converted using `.to_string_lossy()`.
+    pub fn to_json(&self) -> String {
+        let json_os_string = |s: &OsStr| json_string(&s.to_string_lossy());
+        let argv = std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|arg| json_os_string(arg))
+            .collect::<Vec<_>>()
+            .join(",");
+        let current_dir = match &self.current_dir {
+            Some(current_dir) => json_os_string(current_dir.as_os_str()),
+            None => "null".to_owned(),
+        };
+        let envs = self
+            .envs
+            .iter()
+            .map(|(key, value)| {
+                format!(
+                    "{}:{}",
+                    json_os_string(key),
+                    match value {
+                        Some(value) => json_os_string(value),
+                        None => "null".to_owned(),
+                    }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let start_time = self
+            .start_time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let duration = match self.duration {
+            Some(duration) => format!("{:.6}", duration.as_secs_f64()),
+            None => "null".to_owned(),
+        };
+        let exit_code = match self.exit_status.and_then(|status| status.code()) {
+            Some(code) => code.to_string(),
+            None => "null".to_owned(),
+        };
+        let success = match (self.exit_status, &self.error) {
+            (Some(status), _) => status.success().to_string(),
+            (None, Some(_)) => "false".to_owned(),
+            (None, None) => "null".to_owned(),
+        };
+        let error = match &self.error {
+            Some(error) => json_string(error),
+            None => "null".to_owned(),
+        };
+        format!(
+            r#"{{"argv":[{}],"cwd":{},"env":{{{}}},"env_cleared":{},"start_time":{:.6},"duration":{},"exit_code":{},"success":{},"error":{}}}"#,
+            argv,
+            current_dir,
+            envs,
+            self.env_cleared,
+            start_time,
+            duration,
+            exit_code,
+            success,
+            error
+        )
+    }
+}
+
+/// The commands run using `PrintableShellCommand`'s `.spawn()`, `.status()`,
+/// or `.output()` while recording is active. Commands are listed in the order
+/// they finished (or were spawned, for `.spawn()`).
+///
+/// Recording is process-wide, so that a program can explain everything it ran
+/// (e.g. for an `--explain` flag).
+#[derive(Clone, Debug, Default)]
+pub struct Recording {
+    pub commands: Vec<RecordedCommand>,
+}
+
+impl Recording {
+    /// Starts recording for the current process, discarding any commands
+    /// recorded so far.
+    pub fn start() {
+        *RECORDING.lock().unwrap() = Some(Recording::default());
+    }
+
+    /// Stops recording and returns the recorded commands.
+    pub fn stop() -> Recording {
+        RECORDING.lock().unwrap().take().unwrap_or_default()
+    }
+
+    /// Returns the commands recorded so far, without stopping recording.
+    pub fn snapshot() -> Recording {
+        RECORDING.lock().unwrap().clone().unwrap_or_default()
+    }
+
+    pub fn is_active() -> bool {
+        RECORDING.lock().unwrap().is_some()
+    }
+
+    /// A script that runs the recorded commands again. Commands that cannot be
+    /// rendered (e.g. because they are not valid UTF-8) are included as
+    /// comments, and commands that failed to run are preceded by a comment
+    /// with the error.
+    pub fn to_shell_script(&self) -> ShellScript {
+        let mut shell_script = ShellScript::new();
+        for command in &self.commands {
+            let section = match &command.script_section {
+                Ok(section) => section.clone(),
+                Err(error) => format!(
+                    "# Skipped ({}): {:?}",
+                    error,
+                    std::iter::once(&command.program)
+                        .chain(&command.args)
+                        .collect::<Vec<_>>()
+                ),
+            };
+            let section = match &command.error {
+                Some(error) => format!("# Failed to run: {}\n{}", error, section),
+                None => section,
+            };
+            shell_script.add_section(section);
+        }
+        shell_script
+    }
+
+    /// The recorded commands in JSON Lines format (one JSON object per line).
+    pub fn to_json_lines(&self) -> String {
+        self.commands
+            .iter()
+            .map(|command| command.to_json() + "\n")
+            .collect()
+    }
+
+    pub fn write_json_lines_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json_lines())
+    }
+}
+
+/// Records the command if recording is active. Commands that fail to run are
+/// recorded (with their error) before the error is returned.
+fn record<T>(
+    command: &mut PrintableShellCommand,
+    run: impl FnOnce(&mut Command) -> io::Result<T>,
+    exit_status: impl Fn(&T) -> Option<ExitStatus>,
+) -> io::Result<T> {
+    if !Recording::is_active() {
+        return run(command);
+    }
+    let mut recorded_command = RecordedCommand::new(command, SystemTime::now());
+    let start = Instant::now();
+    let result = run(command);
+    match &result {
+        Ok(result) => {
+            if let Some(status) = exit_status(result) {
+                recorded_command.duration = Some(start.elapsed());
+                recorded_command.exit_status = Some(status);
+            }
+        }
+        Err(error) => recorded_command.error = Some(error.to_string()),
+    }
+    if let Some(recording) = RECORDING.lock().unwrap().as_mut() {
+        recording.commands.push(recorded_command);
+    }
+    result
+}
+
+/// These shadow the corresponding methods of `Command`, so that the command is
+/// recorded if recording is active (see `Recording::start()`).
+impl PrintableShellCommand {
+    pub fn spawn(&mut self) -> io::Result<Child> {
+        record(self, Command::spawn, |_| None)
+    }
+
+    pub fn status(&mut self) -> io::Result<ExitStatus> {
+        record(self, Command::status, |status| Some(*status))
+    }
+
+    pub fn output(&mut self) -> io::Result<Output> {
+        record(self, Command::output, |output| Some(output.status))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{ffi::OsStr, io};
+
+    use crate::{PrintableShellCommand, Recording};
+
+    #[test]
+    fn recording() -> io::Result<()> {
+        Recording::start();
+        let mut printf = PrintableShellCommand::new("printf");
+        printf
+            .args_with_comment(["%s\n", "hello world"], "greeting")
+            .env("LC_ALL", "C")
+            .env_remove("PRINTABLE_SHELL_COMMAND_TEST_UNSET");
+        assert_eq!(printf.output()?.stdout, b"hello world\n");
+        let mut sh = PrintableShellCommand::new("sh");
+        sh.args(["-c", "exit 3"]).current_dir("/");
+        sh.env_clear();
+        assert_eq!(sh.status()?.code(), Some(3));
+        let mut missing = PrintableShellCommand::new("printable-shell-command-test-missing");
+        assert!(missing.status().is_err());
+        let mut env = PrintableShellCommand::new("env");
+        env.arg("true").env("NOT A NAME", "1");
+        assert!(env.status()?.success());
+        let recording = Recording::stop();
+        assert!(!Recording::is_active());
+
+        let commands: Vec<_> = recording
+            .commands
+            .iter()
+            .filter(|command| {
+                [
+                    "printf",
+                    "sh",
+                    "printable-shell-command-test-missing",
+                    "env",
+                ]
+                .contains(&command.program.to_str().unwrap_or_default())
+            })
+            .collect();
+        assert_eq!(commands.len(), 4);
+        assert_eq!(commands[1].args, [OsStr::new("-c"), OsStr::new("exit 3")]);
+        assert_eq!(commands[1].current_dir, Some("/".into()));
+        assert_eq!(commands[1].exit_status.unwrap().code(), Some(3));
+        assert!(commands[1].env_cleared);
+        assert!(commands[0].duration.is_some());
+
+        let json = commands[0].to_json();
+        assert!(json.starts_with(
+            r#"{"argv":["printf","%s\n","hello world"],"cwd":null,"env":{"LC_ALL":"C","PRINTABLE_SHELL_COMMAND_TEST_UNSET":null},"env_cleared":false,"start_time":"#
+        ));
+        assert!(json.ends_with(r#","exit_code":0,"success":true,"error":null}"#));
+        assert!(commands[2].exit_status.is_none());
+        assert!(commands[2].error.is_some());
+        assert!(commands[2]
+            .to_json()
+            .contains(r#","exit_code":null,"success":false,"error":""#));
+
+        let script = recording.to_shell_script().to_string();
+        assert!(script.contains(
+            r#"(
+  export LC_ALL=C
+  unset PRINTABLE_SHELL_COMMAND_TEST_UNSET
+  printf \
+    $'%s\n' 'hello world' `# greeting`
+)"#
+        ));
+        assert!(script.contains(
+            r#"(
+  cd /
+  env \
+    -i sh \
+    -c 'exit 3'
+)"#
+        ));
+        assert!(script.contains("# Failed to run: "));
+        assert!(script.contains("\nprintable-shell-command-test-missing\n"));
+        assert!(
+            script.contains(r#"# Skipped (Invalid env var name: "NOT A NAME"): ["env", "true"]"#)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+    path::{Path, PathBuf},
+    process::{ExitStatus, Output},
+    sync::Mutex,
+};
+
+use crate::{
+    ArgumentLineWrapping, FormattingOptions, PrintableShellCommand, ShellPrintableWithOptions,
+};
+
+/// Runs commands. Code that takes a `&dyn CommandRunner` (or a generic
+/// `impl CommandRunner`) can be tested using `FakeCommandRunner` instead of
+/// spawning real processes.
+pub trait CommandRunner {
+    fn output(&self, command: &mut PrintableShellCommand) -> io::Result<Output>;
+
+    fn status(&self, command: &mut PrintableShellCommand) -> io::Result<ExitStatus> {
+        Ok(self.output(command)?.status)
+    }
+}
+
+/// Runs commands as real processes (and records them, if recording is active).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealCommandRunner;
+
+impl CommandRunner for RealCommandRunner {
+    fn output(&self, command: &mut PrintableShellCommand) -> io::Result<Output> {
+        command.output()
+    }
+
+    fn status(&self, command: &mut PrintableShellCommand) -> io::Result<ExitStatus> {
+        command.status()
+    }
+}
+
+/// An element of an `ArgvPattern`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArgPattern {
+    Exact(OsString),
+    /// Matches any single arg.
+    Any,
+    /// Matches any number of args (including none).
+    Rest,
+}
+
+/// A pattern for the program and args of a command.
+///
+/// When constructed from strings, `*` matches any single arg and `**` matches
+/// any number of args. For example, `["git", "commit", "**"]` matches any
+/// `git commit` invocation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArgvPattern(pub Vec<ArgPattern>);
+
+impl ArgvPattern {
+    pub fn matches(&self, argv: &[&OsStr]) -> bool {
+        fn matches_from(patterns: &[ArgPattern], argv: &[&OsStr]) -> bool {
+            match patterns.split_first() {
+                None => argv.is_empty(),
+                Some((ArgPattern::Rest, rest)) => {
+                    (0..=argv.len()).any(|skip| matches_from(rest, &argv[skip..]))
+                }
+                Some((pattern, rest)) => match argv.split_first() {
+                    None => false,
+                    Some((arg, argv_rest)) => {
+                        (match pattern {
+                            ArgPattern::Exact(expected) => expected == arg,
+                            _ => true,
+                        }) && matches_from(rest, argv_rest)
+                    }
+                },
+            }
+        }
+        matches_from(&self.0, argv)
+    }
+}
+
+impl<S: AsRef<OsStr>, I: IntoIterator<Item = S>> From<I> for ArgvPattern {
+    fn from(patterns: I) -> Self {
+        Self(
+            patterns
+                .into_iter()
+                .map(|pattern| match pattern.as_ref().to_str() {
+                    Some("*") => ArgPattern::Any,
+                    Some("**") => ArgPattern::Rest,
+                    _ => ArgPattern::Exact(pattern.as_ref().to_owned()),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A command that was passed to a `FakeCommandRunner`.
+#[derive(Clone, Debug)]
+pub struct FakeInvocation {
+    pub program: OsString,
+    pub args: Vec<OsString>,
+    pub current_dir: Option<PathBuf>,
+    pub envs: Vec<(OsString, Option<OsString>)>,
+    /// The command printed using the default formatting options (ignoring the
+    /// process default), for stable assertions.
+    pub printable_invocation: String,
+}
+
+/// A `CommandRunner` that does not spawn processes. It records each command and
+/// returns the output of the first response whose pattern matches the command.
+/// Commands that match no pattern fail with `io::ErrorKind::NotFound`.
+#[derive(Debug, Default)]
+pub struct FakeCommandRunner {
+    responses: Vec<(ArgvPattern, Output)>,
+    invocations: Mutex<Vec<FakeInvocation>>,
+}
+
+impl FakeCommandRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `output` for commands whose program and args match `pattern`.
+    pub fn respond<P: Into<ArgvPattern>>(&mut self, pattern: P, output: Output) -> &mut Self {
+        self.responses.push((pattern.into(), output));
+        self
+    }
+
+    /// Like `respond(…)`, with an `Output` constructed by `fake_output(…)`.
+    #[cfg(any(unix, windows))]
+    pub fn respond_with<P: Into<ArgvPattern>>(
+        &mut self,
+        pattern: P,
+        exit_code: i32,
+        stdout: impl Into<Vec<u8>>,
+        stderr: impl Into<Vec<u8>>,
+    ) -> &mut Self {
+        self.respond(pattern, fake_output(exit_code, stdout, stderr))
+    }
+
+    /// The commands run so far, in order.
+    pub fn invocations(&self) -> Vec<FakeInvocation> {
+        self.invocations.lock().unwrap().clone()
+    }
+
+    /// The printable form of each command run so far, in order.
+    pub fn printable_invocations(&self) -> Vec<String> {
+        self.invocations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|invocation| invocation.printable_invocation.clone())
+            .collect()
+    }
+}
+
+impl CommandRunner for FakeCommandRunner {
+    fn output(&self, command: &mut PrintableShellCommand) -> io::Result<Output> {
+        self.invocations.lock().unwrap().push(FakeInvocation {
+            program: command.get_program().to_owned(),
+            args: command.get_args().map(OsStr::to_owned).collect(),
+            current_dir: command.get_current_dir().map(Path::to_owned),
+            envs: command
+                .get_envs()
+                .map(|(key, value)| (key.to_owned(), value.map(OsStr::to_owned)))
+                .collect(),
+            printable_invocation: command
+                .printable_invocation_string_lossy_with_options(FormattingOptions::default()),
+        });
+        let argv: Vec<&OsStr> = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .collect();
+        match self
+            .responses
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&argv))
+        {
+            Some((_, output)) => Ok(output.clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No fake response for: {}",
+                    command.printable_invocation_string_lossy_with_options(
+                        FormattingOptions::default()
+                            .argument_line_wrapping(ArgumentLineWrapping::Inline)
+                    )
+                ),
+            )),
+        }
+    }
+}
+
+/// An `ExitStatus` for a process that exited with `exit_code`.
+///
+/// On Unix, only the low 8 bits of `exit_code` are kept (as for a real
+/// process), so e.g. `256` results in an exit code of `0`. (`ExitStatus` can
+/// only be constructed on Unix and Windows.)
+#[cfg(any(unix, windows))]
+pub fn fake_exit_status(exit_code: i32) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        ExitStatus::from_raw((exit_code & 0xff) << 8)
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+        ExitStatus::from_raw(exit_code as u32)
+    }
+}
+
+#[cfg(any(unix, windows))]
+pub fn fake_output(
+    exit_code: i32,
+    stdout: impl Into<Vec<u8>>,
+    stderr: impl Into<Vec<u8>>,
+) -> Output {
+    Output {
+        status: fake_exit_status(exit_code),
+        stdout: stdout.into(),
+        stderr: stderr.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, io};
+
+    use crate::{
+        ArgvPattern, CommandRunner, FakeCommandRunner, PrintableShellCommand, RealCommandRunner,
+    };
+
+    fn current_branch(runner: &dyn CommandRunner) -> io::Result<String> {
+        let mut command = PrintableShellCommand::new("git");
+        command.args(["rev-parse", "--abbrev-ref", "HEAD"]);
+        Ok(
+            String::from_utf8_lossy(&runner.output(&mut command)?.stdout)
+                .trim()
+                .to_owned(),
+        )
+    }
+
+    #[cfg(any(unix, windows))]
+    #[test]
+    fn fake_runner() -> io::Result<()> {
+        let mut runner = FakeCommandRunner::new();
+        runner
+            .respond_with(["git", "rev-parse", "**"], 0, "main\n", "")
+            .respond_with(["git", "push", "*"], 1, "", "rejected\n");
+        assert_eq!(current_branch(&runner)?, "main");
+
+        let mut push = PrintableShellCommand::new("git");
+        push.arg("push").arg("origin").current_dir("/src");
+        assert_eq!(runner.status(&mut push)?.code(), Some(1));
+
+        let mut fetch = PrintableShellCommand::new("git");
+        fetch.arg("fetch");
+        assert_eq!(
+            runner.output(&mut fetch).unwrap_err().to_string(),
+            "No fake response for: git fetch"
+        );
+
+        assert_eq!(
+            runner.printable_invocations(),
+            [
+                "git \\\n  rev-parse --abbrev-ref HEAD",
+                "git \\\n  push \\\n  origin",
+                "git \\\n  fetch"
+            ]
+        );
+        assert_eq!(runner.invocations()[1].current_dir, Some("/src".into()));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn real_runner() -> io::Result<()> {
+        let mut command = PrintableShellCommand::new("sh");
+        command.args(["-c", "printf hello; exit 3"]);
+        let output = RealCommandRunner.output(&mut command)?;
+        assert_eq!(output.stdout, b"hello");
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(RealCommandRunner.status(&mut command)?.code(), Some(3));
+
+        let mut missing = PrintableShellCommand::new("printable-shell-command-test-missing");
+        assert!(RealCommandRunner.status(&mut missing).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fake_exit_statuses() {
+        use crate::fake_exit_status;
+
+        assert_eq!(fake_exit_status(0).code(), Some(0));
+        assert_eq!(fake_exit_status(255).code(), Some(255));
+        assert_eq!(fake_exit_status(256).code(), Some(0));
+        assert_eq!(fake_exit_status(-1).code(), Some(255));
+    }
+
+    #[test]
+    fn argv_patterns() {
+        let pattern: ArgvPattern = ["cargo", "*", "**", "--release"].into();
+        let matches =
+            |argv: &[&str]| pattern.matches(&argv.iter().map(OsStr::new).collect::<Vec<_>>());
+        assert!(matches(&["cargo", "build", "--release"]));
+        assert!(matches(&["cargo", "test", "-p", "foo", "--release"]));
+        assert!(!matches(&["cargo", "--release"]));
+        assert!(!matches(&["cargo", "build", "--release", "-v"]));
+        assert!(!matches(&["rustc", "build", "--release"]));
+    }
+}
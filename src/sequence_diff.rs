@@ -0,0 +1,64 @@
+/// One step of a diff between two sequences.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum DiffOp<'a, T> {
+    Unchanged(&'a T),
+    Removed(&'a T),
+    Added(&'a T),
+}
+
+/// Diffs `old` and `new` using their longest common subsequence. Removals are
+/// listed before additions at each position where the sequences differ.
+pub(crate) fn diff_sequences<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> Vec<DiffOp<'a, T>> {
+    // `lcs_lengths[i][j]` is the length of the LCS of `old[i..]` and `new[j..]`.
+    let mut lcs_lengths = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs_lengths[i][j] = if old[i] == new[j] {
+                lcs_lengths[i + 1][j + 1] + 1
+            } else {
+                lcs_lengths[i + 1][j].max(lcs_lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(DiffOp::Unchanged(&old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len()
+            && (j == new.len() || lcs_lengths[i + 1][j] >= lcs_lengths[i][j + 1])
+        {
+            ops.push(DiffOp::Removed(&old[i]));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Added(&new[j]));
+            j += 1;
+        }
+    }
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_sequences, DiffOp};
+
+    #[test]
+    fn diff() {
+        assert_eq!(
+            diff_sequences(&["a", "b", "c", "d"], &["a", "x", "c", "d", "e"]),
+            [
+                DiffOp::Unchanged(&"a"),
+                DiffOp::Removed(&"b"),
+                DiffOp::Added(&"x"),
+                DiffOp::Unchanged(&"c"),
+                DiffOp::Unchanged(&"d"),
+                DiffOp::Added(&"e"),
+            ]
+        );
+        assert_eq!(diff_sequences::<u8>(&[], &[]), []);
+        assert_eq!(diff_sequences(&[1], &[]), [DiffOp::Removed(&1)]);
+    }
+}
@@ -1,6 +1,9 @@
 use std::str::Utf8Error;
 
-use crate::FormattingOptions;
+use crate::{
+    format::{html_code_block, markdown_code_block},
+    FormattingOptions,
+};
 
 pub trait ShellPrintable {
     fn printable_invocation_string(&self) -> Result<String, Utf8Error>;
@@ -30,6 +33,46 @@ pub trait ShellPrintableWithOptions {
         formatting_options: FormattingOptions,
     ) -> String;
 
+    /// The printable invocation, wrapped in a fenced Markdown code block.
+    fn printable_invocation_markdown_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> Result<String, Utf8Error> {
+        Ok(markdown_code_block(
+            &self.printable_invocation_string_with_options(formatting_options)?,
+        ))
+    }
+    // Calls `.to_string_lossy()` on the program name and args.
+    fn printable_invocation_markdown_lossy_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> String {
+        markdown_code_block(
+            &self.printable_invocation_string_lossy_with_options(formatting_options),
+        )
+    }
+
+    /// The printable invocation as an HTML `<pre><code>` block. For `Command`
+    /// and `PrintableShellCommand`, each word is wrapped in a `<span>` with one
+    /// of the classes `program`, `flag`, `value`, or `comment`, plus the class
+    /// `quoted` if it was quoted. (The default implementation only escapes the
+    /// printable invocation.)
+    fn printable_invocation_html_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> Result<String, Utf8Error> {
+        Ok(html_code_block(
+            &self.printable_invocation_string_with_options(formatting_options)?,
+        ))
+    }
+    // Calls `.to_string_lossy()` on the program name and args.
+    fn printable_invocation_html_lossy_with_options(
+        &self,
+        formatting_options: FormattingOptions,
+    ) -> String {
+        html_code_block(&self.printable_invocation_string_lossy_with_options(formatting_options))
+    }
+
     // Print the invocation to `stdout`.`
     fn print_invocation_with_options(
         &mut self,
@@ -53,3 +96,43 @@ pub trait ShellPrintableWithOptions {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::Utf8Error;
+
+    use super::ShellPrintableWithOptions;
+    use crate::FormattingOptions;
+
+    /// Implements only the required methods.
+    struct Custom;
+
+    impl ShellPrintableWithOptions for Custom {
+        fn printable_invocation_string_with_options(
+            &self,
+            _formatting_options: FormattingOptions,
+        ) -> Result<String, Utf8Error> {
+            Ok("echo '<hi>'".to_owned())
+        }
+
+        fn printable_invocation_string_lossy_with_options(
+            &self,
+            _formatting_options: FormattingOptions,
+        ) -> String {
+            "echo '<hi>'".to_owned()
+        }
+    }
+
+    #[test]
+    fn default_html() -> Result<(), Utf8Error> {
+        assert_eq!(
+            Custom.printable_invocation_html_with_options(FormattingOptions::default())?,
+            "<pre><code class=\"language-shell\">echo &#39;&lt;hi&gt;&#39;</code></pre>"
+        );
+        assert_eq!(
+            Custom.printable_invocation_html_lossy_with_options(FormattingOptions::default()),
+            "<pre><code class=\"language-shell\">echo &#39;&lt;hi&gt;&#39;</code></pre>"
+        );
+        Ok(())
+    }
+}
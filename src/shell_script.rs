@@ -0,0 +1,290 @@
+use std::{
+    fmt::{self, Display},
+    fs, io,
+    path::Path,
+};
+
+use crate::{
+    format::{
+        check_env_var_name, conditional_escape, os_str_to_str, sh_cd_statement, EscapeOptions,
+    },
+    FormattingOptions, PrintableShellCommand, ShellDialect, ShellPrintableWithOptions,
+};
+
+const SHEBANG: &str = "#!/usr/bin/env bash";
+const SUBSHELL_INDENTATION: &str = "  ";
+
+/// A standalone `bash` script that runs a sequence of commands, e.g. to let
+/// someone reproduce what a program did without running the program itself.
+///
+/// Commands are rendered when they are added. A command with a working
+/// directory or env vars is run in a subshell that sets them (or using `env -i
+/// KEY=value …`, if the command clears its environment):
+///
+/// ```text
+/// (
+///   cd /tmp
+///   export FFREPORT=1
+///   ffmpeg \
+///     -i input.mp4 \
+///     output.mov
+/// )
+/// ```
+#[derive(Clone, Debug)]
+pub struct ShellScript {
+    formatting_options: FormattingOptions,
+    trace: bool,
+    sections: Vec<String>,
+}
+
+impl Default for ShellScript {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShellScript {
+    pub fn new() -> Self {
+        Self::with_options(FormattingOptions::default())
+    }
+
+    /// Commands are printed using `formatting_options`, except that the
+    /// dialect is always `bash` and paths are never hyperlinked.
+    pub fn with_options(formatting_options: FormattingOptions) -> Self {
+        Self {
+            formatting_options: formatting_options
+                .dialect(ShellDialect::Bash)
+                .hyperlink_paths(false),
+            trace: false,
+            sections: vec![],
+        }
+    }
+
+    /// Adds `set -x`, so that `bash` prints each command as it is run.
+    pub fn set_trace(&mut self, trace: bool) -> &mut Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Adds a command, together with its working directory and env vars.
+    ///
+    /// This returns an error if an env var name is not a valid shell variable
+    /// name, or if the command is not valid UTF-8.
+    pub fn add_command(&mut self, command: &PrintableShellCommand) -> io::Result<&mut Self> {
+        let section = self.script_section(command)?;
+        self.sections.push(section);
+        Ok(self)
+    }
+
+    /// Adds each of `commands` in order.
+    pub fn add_commands<'a, I: IntoIterator<Item = &'a PrintableShellCommand>>(
+        &mut self,
+        commands: I,
+    ) -> io::Result<&mut Self> {
+        for command in commands {
+            self.add_command(command)?;
+        }
+        Ok(self)
+    }
+
+    /// Adds text that was already rendered using `script_section(…)`.
+    pub(crate) fn add_section(&mut self, section: String) -> &mut Self {
+        self.sections.push(section);
+        self
+    }
+
+    /// Renders the lines of the script that run `command`.
+    pub(crate) fn script_section(&self, command: &PrintableShellCommand) -> io::Result<String> {
+        if command.is_env_cleared() {
+            let mut command = command.duplicate();
+            command.move_env_into_env_prefix()?;
+            return self.script_section(&command);
+        }
+        let to_io_error = |error| io::Error::new(io::ErrorKind::InvalidData, error);
+        let mut statements: Vec<String> = vec![];
+        if let Some(current_dir) = command.get_current_dir() {
+            let current_dir = os_str_to_str(current_dir.as_os_str())?;
+            statements.push(sh_cd_statement(current_dir, &self.escape(current_dir)));
+        }
+        for (key, value) in command.get_envs() {
+            let key = os_str_to_str(key)?;
+            check_env_var_name(key)?;
+            statements.push(match value {
+                Some(value) => format!("export {}={}", key, self.escape(os_str_to_str(value)?)),
+                None => format!("unset {}", key),
+            });
+        }
+        Ok(if statements.is_empty() {
+            command
+                .printable_invocation_string_with_options(self.formatting_options.clone())
+                .map_err(to_io_error)?
+        } else {
+            let outer_indentation = self
+                .formatting_options
+                .main_indentation
+                .clone()
+                .unwrap_or_default();
+            let main_indentation = format!("{}{}", outer_indentation, SUBSHELL_INDENTATION);
+            let invocation = command
+                .printable_invocation_string_with_options(
+                    self.formatting_options
+                        .clone()
+                        .main_indentation(&main_indentation),
+                )
+                .map_err(to_io_error)?;
+            let mut lines = vec![format!("{}(", outer_indentation)];
+            lines.extend(
+                statements
+                    .into_iter()
+                    .map(|statement| format!("{}{}", main_indentation, statement)),
+            );
+            lines.push(invocation);
+            lines.push(format!("{})", outer_indentation));
+            lines.join("\n")
+        })
+    }
+
+    /// Writes the script to `path`. On Unix, the file is also made executable.
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(&path, self.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        }
+        Ok(())
+    }
+
+    fn escape(&self, s: &str) -> String {
+        conditional_escape(
+            s,
+            EscapeOptions {
+                is_main_command: false,
+                dialect: ShellDialect::Bash,
+                sanitize: self
+                    .formatting_options
+                    .sanitize_untrusted_characters
+                    .unwrap_or(false),
+            },
+        )
+    }
+}
+
+impl Display for ShellScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", SHEBANG)?;
+        writeln!(f, "set -euo pipefail")?;
+        if self.trace {
+            writeln!(f, "set -x")?;
+        }
+        for section in &self.sections {
+            writeln!(f)?;
+            writeln!(f, "{}", section)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use crate::{FormattingOptions, PrintableShellCommand, ShellScript};
+
+    #[test]
+    fn script() -> io::Result<()> {
+        let mut mkdir = PrintableShellCommand::new("mkdir");
+        mkdir.args(["-p", "./test/slow mo"]);
+        let mut ffmpeg = PrintableShellCommand::new("ffmpeg");
+        ffmpeg
+            .args_with_comment(["-i", "./My video.mp4"], "input")
+            .arg("./slow mo/It's slow-mo.mov");
+        ffmpeg
+            .current_dir("./test")
+            .env("FFREPORT", "file=ffmpeg report.log")
+            .env_remove("TERM");
+        let mut script = ShellScript::new();
+        script.add_commands([&mkdir, &ffmpeg])?;
+        assert_eq!(
+            script.to_string(),
+            r#"#!/usr/bin/env bash
+set -euo pipefail
+
+mkdir \
+  -p './test/slow mo'
+
+(
+  cd ./test
+  export FFREPORT='file=ffmpeg report.log'
+  unset TERM
+  ffmpeg \
+    -i './My video.mp4' `# input` \
+    './slow mo/It'\''s slow-mo.mov'
+)
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn script_with_trace_and_options() -> io::Result<()> {
+        let mut echo = PrintableShellCommand::new("echo");
+        echo.args(["hello", "world"]).env("LC_ALL", "C");
+        let mut script = ShellScript::with_options(FormattingOptions::compact());
+        script.set_trace(true).add_command(&echo)?;
+        assert_eq!(
+            script.to_string(),
+            r#"#!/usr/bin/env bash
+set -euo pipefail
+set -x
+
+(
+  export LC_ALL=C
+  echo hello world
+)
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn options_and_invalid_env_var_names() -> io::Result<()> {
+        let mut ls = PrintableShellCommand::new("ls");
+        ls.current_dir("-v");
+        let mut script = ShellScript::with_options(FormattingOptions::compact());
+        script.add_command(&ls)?;
+        assert_eq!(
+            script.to_string(),
+            r#"#!/usr/bin/env bash
+set -euo pipefail
+
+(
+  cd -- -v
+  ls
+)
+"#
+        );
+
+        let mut ls = PrintableShellCommand::new("ls");
+        ls.env_clear().env("LC_ALL", "C").current_dir("/tmp");
+        let mut script = ShellScript::with_options(FormattingOptions::compact());
+        script.add_command(&ls)?;
+        assert_eq!(
+            script.to_string(),
+            r#"#!/usr/bin/env bash
+set -euo pipefail
+
+(
+  cd /tmp
+  env -i LC_ALL=C ls
+)
+"#
+        );
+
+        let mut ls = PrintableShellCommand::new("ls");
+        ls.env("X=1; reboot; Y", "1");
+        let error = ShellScript::new().add_command(&ls).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        Ok(())
+    }
+}
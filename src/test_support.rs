@@ -0,0 +1,156 @@
+//! Helpers for snapshot-testing printed commands. Requires the `test-support`
+//! feature.
+//!
+//! Snapshots are stored in `snapshots/<name>.snap` relative to the manifest
+//! directory of the crate under test. To create or update snapshots, run the
+//! tests with `PRINTABLE_SHELL_COMMAND_UPDATE_SNAPSHOTS=1`.
+
+use std::{env, fs, path::PathBuf};
+
+use crate::sequence_diff::{diff_sequences, DiffOp};
+
+pub const UPDATE_SNAPSHOTS_ENV_VAR: &str = "PRINTABLE_SHELL_COMMAND_UPDATE_SNAPSHOTS";
+
+const SNAPSHOT_DIR: &str = "snapshots";
+const SNAPSHOT_EXTENSION: &str = "snap";
+
+/// Asserts that the printable invocation of a command matches a stored
+/// snapshot:
+///
+/// ```ignore
+/// assert_printable_snapshot!(command, "ffmpeg");
+/// assert_printable_snapshot!(command, FormattingOptions::compact(), "ffmpeg-compact");
+/// ```
+///
+/// If no options are passed, the default formatting options are used (rather
+/// than the process default), so that snapshots do not depend on the
+/// environment.
+#[macro_export]
+macro_rules! assert_printable_snapshot {
+    ($command:expr, $name:expr) => {
+        $crate::assert_printable_snapshot!($command, $crate::FormattingOptions::default(), $name)
+    };
+    ($command:expr, $formatting_options:expr, $name:expr) => {
+        $crate::test_support::assert_snapshot(
+            env!("CARGO_MANIFEST_DIR"),
+            $name,
+            &$crate::ShellPrintableWithOptions::printable_invocation_string_with_options(
+                &$command,
+                $formatting_options,
+            )
+            .expect("Command is not valid UTF-8"),
+        )
+    };
+}
+
+fn snapshot_path(manifest_dir: &str, name: &str) -> PathBuf {
+    PathBuf::from(manifest_dir)
+        .join(SNAPSHOT_DIR)
+        .join(format!("{}.{}", name, SNAPSHOT_EXTENSION))
+}
+
+fn should_update_snapshots() -> bool {
+    matches!(
+        env::var(UPDATE_SNAPSHOTS_ENV_VAR).as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// Panics if `actual` does not match the snapshot `name` (or writes the
+/// snapshot, if `PRINTABLE_SHELL_COMMAND_UPDATE_SNAPSHOTS` is set). Usually
+/// called through `assert_printable_snapshot!(…)`.
+pub fn assert_snapshot(manifest_dir: &str, name: &str, actual: &str) {
+    assert_snapshot_with_update(manifest_dir, name, actual, should_update_snapshots());
+}
+
+fn assert_snapshot_with_update(manifest_dir: &str, name: &str, actual: &str, update: bool) {
+    let path = snapshot_path(manifest_dir, name);
+    // Snapshot files end with a newline (which is not part of the snapshot).
+    let expected = fs::read_to_string(&path)
+        .ok()
+        .map(|contents| contents.strip_suffix('\n').unwrap_or(&contents).to_owned());
+    if expected.as_deref() == Some(actual) {
+        return;
+    }
+    if update {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).expect("Could not create snapshot directory");
+        }
+        fs::write(&path, format!("{}\n", actual)).expect("Could not write snapshot");
+        return;
+    }
+    match expected {
+        None => panic!(
+            "Missing snapshot: {}\nRun with `{}=1` to create it. Actual value:\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV_VAR,
+            actual
+        ),
+        Some(expected) => panic!(
+            "Snapshot does not match: {}\nRun with `{}=1` to update it.\n{}",
+            path.display(),
+            UPDATE_SNAPSHOTS_ENV_VAR,
+            line_diff(&expected, actual)
+        ),
+    }
+}
+
+/// A line-by-line diff, with `-` for removed lines and `+` for added lines.
+pub fn line_diff(expected: &str, actual: &str) -> String {
+    let expected_lines: Vec<&str> = expected.split('\n').collect();
+    let actual_lines: Vec<&str> = actual.split('\n').collect();
+    diff_sequences(&expected_lines, &actual_lines)
+        .into_iter()
+        .map(|op| match op {
+            DiffOp::Unchanged(line) => format!("  {}\n", line),
+            DiffOp::Removed(line) => format!("- {}\n", line),
+            DiffOp::Added(line) => format!("+ {}\n", line),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, panic};
+
+    use super::{assert_snapshot_with_update, line_diff, snapshot_path};
+
+    #[test]
+    fn diff() {
+        assert_eq!(
+            line_diff("rsync \\\n  -avz \\\n  src", "rsync \\\n  -av \\\n  src"),
+            "  rsync \\\n-   -avz \\\n+   -av \\\n    src\n"
+        );
+    }
+
+    #[test]
+    fn update_and_mismatch() {
+        let dir = env::temp_dir().join("printable-shell-command-snapshot-test");
+        let _ = fs::remove_dir_all(&dir);
+        let manifest_dir = dir.to_str().unwrap();
+
+        let missing = panic::catch_unwind(|| {
+            assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -avz", false)
+        });
+        assert!(missing.is_err());
+
+        assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -avz", true);
+        assert_eq!(
+            fs::read_to_string(snapshot_path(manifest_dir, "rsync")).unwrap(),
+            "rsync \\\n  -avz\n"
+        );
+        assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -avz", false);
+
+        let mismatch = panic::catch_unwind(|| {
+            assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -av", false)
+        })
+        .unwrap_err();
+        let message = mismatch.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("Snapshot does not match: "));
+        assert!(message.ends_with("  rsync \\\n-   -avz\n+   -av\n"));
+
+        assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -av", true);
+        assert_snapshot_with_update(manifest_dir, "rsync", "rsync \\\n  -av", false);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
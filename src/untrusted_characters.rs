@@ -0,0 +1,114 @@
+/// Characters that can make a printed command look different from what is
+/// actually executed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UntrustedCharacterKind {
+    /// C0 and C1 control characters (including ESC, BEL, CR, and newlines),
+    /// `DEL`, and Unicode line/paragraph separators. These can move the cursor
+    /// or change what a terminal displays.
+    Control,
+    /// Unicode bidirectional formatting characters, which can reorder how text
+    /// is displayed ("Trojan Source" attacks).
+    Bidi,
+    /// Zero-width characters, which are invisible when displayed.
+    ZeroWidth,
+}
+
+/// Where an untrusted character was found.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UntrustedCharacterLocation {
+    Program,
+    /// The index of the arg (not counting the program).
+    Arg(usize),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UntrustedCharacter {
+    pub location: UntrustedCharacterLocation,
+    pub character: char,
+    pub kind: UntrustedCharacterKind,
+}
+
+pub(crate) fn untrusted_character_kind(c: char) -> Option<UntrustedCharacterKind> {
+    match c {
+        '\u{0}'..='\u{1f}' | '\u{7f}'..='\u{9f}' | '\u{2028}' | '\u{2029}' => {
+            Some(UntrustedCharacterKind::Control)
+        }
+        '\u{61c}' | '\u{200e}' | '\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}' => {
+            Some(UntrustedCharacterKind::Bidi)
+        }
+        '\u{200b}'..='\u{200d}' | '\u{2060}' | '\u{feff}' => {
+            Some(UntrustedCharacterKind::ZeroWidth)
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn contains_untrusted_characters(s: &str) -> bool {
+    s.chars().any(|c| untrusted_character_kind(c).is_some())
+}
+
+pub(crate) fn find_untrusted_characters(
+    s: &str,
+    location: UntrustedCharacterLocation,
+) -> impl Iterator<Item = UntrustedCharacter> + '_ {
+    s.chars().filter_map(move |character| {
+        untrusted_character_kind(character).map(|kind| UntrustedCharacter {
+            location,
+            character,
+            kind,
+        })
+    })
+}
+
+/// Replaces untrusted characters with visible `\u{…}` escapes. This is only
+/// suitable for text that is not interpreted by the shell (e.g. comments).
+pub(crate) fn make_untrusted_characters_visible(s: &str) -> String {
+    s.chars()
+        .map(|c| match untrusted_character_kind(c) {
+            Some(_) => format!("\\u{{{:x}}}", c as u32),
+            None => c.to_string(),
+        })
+        .collect()
+}
+
+/// Replaces untrusted characters with visible placeholders like `<U+202E>`,
+/// for dialects that have no way to escape them (`cmd.exe`). The result no
+/// longer round-trips to the original string.
+pub(crate) fn replace_untrusted_characters_with_placeholders(s: &str) -> String {
+    s.chars()
+        .map(|c| match untrusted_character_kind(c) {
+            Some(_) => format!("<U+{:04X}>", c as u32),
+            None => c.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{untrusted_character_kind, UntrustedCharacterKind};
+
+    #[test]
+    fn kinds() {
+        for c in ['\x1b', '\x07', '\r', '\n', '\u{9b}', '\u{2028}'] {
+            assert_eq!(
+                untrusted_character_kind(c),
+                Some(UntrustedCharacterKind::Control)
+            );
+        }
+        for c in ['\u{202e}', '\u{2066}', '\u{200f}', '\u{61c}'] {
+            assert_eq!(
+                untrusted_character_kind(c),
+                Some(UntrustedCharacterKind::Bidi)
+            );
+        }
+        for c in ['\u{200b}', '\u{200d}', '\u{feff}'] {
+            assert_eq!(
+                untrusted_character_kind(c),
+                Some(UntrustedCharacterKind::ZeroWidth)
+            );
+        }
+        for c in ['a', ' ', 'é', '🙈', '\u{a0}'] {
+            assert_eq!(untrusted_character_kind(c), None);
+        }
+    }
+}
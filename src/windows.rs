@@ -0,0 +1,172 @@
+/// Characters that `cmd.exe` interprets unless escaped with `^`.
+const CMD_METACHARACTERS: &[char] = &['(', ')', '%', '!', '^', '"', '<', '>', '&', '|'];
+
+/// Quotes an arg so that it is parsed back into the same string by the MSVCRT
+/// (and `CommandLineToArgvW`) rules:
+///
+/// - Quoting is only needed for empty args and args containing whitespace or
+///   `"`.
+/// - Inside quotes, backslashes are literal unless they precede a `"`, in which
+///   case they must be doubled (and the `"` itself escaped).
+pub(crate) fn quote_windows_arg(arg: &str, force_quotes: bool) -> String {
+    if !force_quotes && !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_owned();
+    }
+    let mut quoted = "\"".to_owned();
+    let mut num_backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => {
+                num_backslashes += 1;
+                continue;
+            }
+            '"' => {
+                quoted.push_str(&"\\".repeat(num_backslashes * 2 + 1));
+            }
+            _ => {
+                quoted.push_str(&"\\".repeat(num_backslashes));
+            }
+        }
+        quoted.push(c);
+        num_backslashes = 0;
+    }
+    quoted.push_str(&"\\".repeat(num_backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes the program name. The first entry of a Windows command line is
+/// parsed differently from the rest: it ends at the first whitespace (or, if
+/// quoted, at the next `"`), and backslashes are never escapes.
+pub(crate) fn quote_windows_program(program: &str, force_quotes: bool) -> String {
+    if force_quotes || program.is_empty() || program.contains([' ', '\t']) {
+        format!("\"{}\"", program)
+    } else {
+        program.to_owned()
+    }
+}
+
+/// Escapes every `cmd.exe` metacharacter (including `"`) with `^`, so that
+/// `cmd.exe` passes the text through to the program unchanged.
+pub(crate) fn escape_cmd_metacharacters(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if CMD_METACHARACTERS.contains(&c) {
+            escaped.push('^');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub(crate) fn escape_for_cmd(s: &str, is_main_command: bool, force_quotes: bool) -> String {
+    let quoted = if is_main_command {
+        quote_windows_program(s, force_quotes)
+    } else {
+        quote_windows_arg(s, force_quotes)
+    };
+    escape_cmd_metacharacters(&quoted)
+}
+
+/// The command line that Windows programs receive (e.g. from `CreateProcess`)
+/// for the given program and args.
+pub(crate) fn windows_command_line<'a>(
+    program: &str,
+    args: impl Iterator<Item = &'a str>,
+) -> String {
+    let mut command_line = quote_windows_program(program, false);
+    for arg in args {
+        command_line.push(' ');
+        command_line.push_str(&quote_windows_arg(arg, false));
+    }
+    command_line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_cmd_metacharacters, quote_windows_arg, windows_command_line};
+
+    /// Parses args (after the program name) using the MSVCRT rules.
+    fn parse_windows_args(command_line: &str) -> Vec<String> {
+        let mut args = vec![];
+        let mut chars = command_line.chars().peekable();
+        loop {
+            while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+            if chars.peek().is_none() {
+                return args;
+            }
+            let mut arg = String::new();
+            let mut in_quotes = false;
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        let mut num_backslashes = 1;
+                        while chars.next_if_eq(&'\\').is_some() {
+                            num_backslashes += 1;
+                        }
+                        if chars.peek() == Some(&'"') {
+                            arg.push_str(&"\\".repeat(num_backslashes / 2));
+                            if num_backslashes % 2 == 1 {
+                                arg.push(chars.next().unwrap());
+                            }
+                        } else {
+                            arg.push_str(&"\\".repeat(num_backslashes));
+                        }
+                    }
+                    '"' => {
+                        if in_quotes && chars.peek() == Some(&'"') {
+                            arg.push(chars.next().unwrap());
+                        } else {
+                            in_quotes = !in_quotes;
+                        }
+                    }
+                    ' ' | '\t' if !in_quotes => break,
+                    c => arg.push(c),
+                }
+            }
+            args.push(arg);
+        }
+    }
+
+    #[test]
+    fn quoting() {
+        assert_eq!(quote_windows_arg("plain", false), "plain");
+        assert_eq!(quote_windows_arg("plain", true), "\"plain\"");
+        assert_eq!(quote_windows_arg("", false), "\"\"");
+        assert_eq!(quote_windows_arg("a b", false), "\"a b\"");
+        assert_eq!(
+            quote_windows_arg(r"C:\Program Files\", false),
+            r#""C:\Program Files\\""#
+        );
+        assert_eq!(quote_windows_arg(r"C:\Windows\", false), r"C:\Windows\");
+        assert_eq!(quote_windows_arg(r#"say "hi""#, false), r#""say \"hi\"""#);
+        assert_eq!(quote_windows_arg(r#"a\"b"#, false), r#""a\\\"b""#);
+    }
+
+    #[test]
+    fn round_trip() {
+        let args = [
+            "plain",
+            "",
+            "a b",
+            r"C:\Program Files\",
+            r#"say "hi""#,
+            r#"a\\"b\"#,
+            "\ttab",
+            r"\\server\share\",
+        ];
+        assert_eq!(
+            parse_windows_args(&windows_command_line("tool.exe", args.into_iter())[9..]),
+            args
+        );
+    }
+
+    #[test]
+    fn cmd_metacharacters() {
+        assert_eq!(
+            escape_cmd_metacharacters(r#""a b" & echo %PATH% | more > out.txt"#),
+            r#"^"a b^" ^& echo ^%PATH^% ^| more ^> out.txt"#
+        );
+        assert_eq!(escape_cmd_metacharacters("(^!)"), "^(^^^!^)");
+    }
+}
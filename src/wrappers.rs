@@ -0,0 +1,861 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+    path::{self, Path},
+    process::Command,
+    str::Utf8Error,
+    time::Duration,
+};
+
+use crate::{
+    command::copy_env_and_current_dir,
+    format::{
+        check_env_var_name, conditional_escape, os_str_to_str, sh_cd_statement, EscapeOptions,
+        CONTROL_CHARACTER_REGEX,
+    },
+    untrusted_characters::contains_untrusted_characters,
+    PrintableShellCommand, ShellDialect,
+};
+
+/// Double-quotes `s` if it contains single quotes but nothing that is special
+/// inside double quotes. This keeps nested commands readable (e.g.
+/// `"echo 'hi'"` rather than `'echo '\''hi'\'''`). Returns `None` if `s`
+/// should be quoted as usual.
+pub(crate) fn double_quote_nested_command(
+    s: &str,
+    dialect: ShellDialect,
+    sanitize: bool,
+) -> Option<String> {
+    let history_expansion = match dialect {
+        ShellDialect::Posix => false,
+        ShellDialect::Bash | ShellDialect::Zsh => s.contains('!'),
+        ShellDialect::Fish | ShellDialect::PowerShell | ShellDialect::Cmd => return None,
+    };
+    if !s.contains('\'')
+        || s.contains(['"', '$', '`', '\\'])
+        || history_expansion
+        || CONTROL_CHARACTER_REGEX.is_match(s)
+        || (sanitize && contains_untrusted_characters(s))
+    {
+        return None;
+    }
+    Some(format!("\"{}\"", s))
+}
+
+fn escape_for_sh(s: &str, is_main_command: bool) -> String {
+    if !is_main_command {
+        if let Some(quoted) = double_quote_nested_command(s, ShellDialect::Posix, false) {
+            return quoted;
+        }
+    }
+    conditional_escape(
+        s,
+        EscapeOptions {
+            is_main_command,
+            dialect: ShellDialect::Posix,
+            sanitize: false,
+        },
+    )
+}
+
+/// Renders the program and args as a single line of POSIX `sh`, for embedding
+/// in another command. Arg groups and comments are not included.
+fn sh_command_string(command: &Command) -> Result<String, Utf8Error> {
+    let mut words = vec![escape_for_sh(
+        TryInto::<&str>::try_into(command.get_program())?,
+        true,
+    )];
+    for arg in command.get_args() {
+        words.push(escape_for_sh(TryInto::<&str>::try_into(arg)?, false));
+    }
+    Ok(words.join(" "))
+}
+
+/// Like `sh_command_string(…)`, but also sets the working directory and env
+/// vars of the command (for commands that will not run in the current
+/// process environment).
+fn sh_command_string_with_env_and_current_dir(command: &Command) -> io::Result<String> {
+    let mut statements: Vec<String> = vec![];
+    if let Some(current_dir) = command.get_current_dir() {
+        let current_dir = os_str_to_str(current_dir.as_os_str())?;
+        statements.push(sh_cd_statement(
+            current_dir,
+            &escape_for_sh(current_dir, false),
+        ));
+    }
+    let mut assignments = String::new();
+    for (key, value) in command.get_envs() {
+        let key = os_str_to_str(key)?;
+        check_env_var_name(key)?;
+        match value {
+            Some(value) => {
+                assignments += &format!("{}={} ", key, escape_for_sh(os_str_to_str(value)?, false));
+            }
+            None => statements.push(format!("unset {}", key)),
+        }
+    }
+    let command_string = sh_command_string(command)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    statements.push(assignments + &command_string);
+    Ok(statements.join(" && "))
+}
+
+/// A container engine with a `docker`-compatible CLI.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ContainerEngine {
+    Docker,
+    Podman,
+}
+
+impl ContainerEngine {
+    fn program(&self) -> &'static OsStr {
+        OsStr::new(match self {
+            ContainerEngine::Docker => "docker",
+            ContainerEngine::Podman => "podman",
+        })
+    }
+}
+
+impl PrintableShellCommand {
+    /// Returns a command that runs this command using `sh -c '…'`.
+    ///
+    /// The working directory and env vars are set on the returned command.
+    pub fn wrap_in_sh_c(&self) -> Result<PrintableShellCommand, Utf8Error> {
+        self.wrap_in_shell_c("sh")
+    }
+
+    /// Returns a command that runs this command using `bash -c '…'`.
+    ///
+    /// The working directory and env vars are set on the returned command.
+    pub fn wrap_in_bash_c(&self) -> Result<PrintableShellCommand, Utf8Error> {
+        self.wrap_in_shell_c("bash")
+    }
+
+    fn wrap_in_shell_c(&self, shell: &str) -> Result<PrintableShellCommand, Utf8Error> {
+        let mut wrapped = PrintableShellCommand::new(shell);
+        wrapped.nested_command_args(["-c", &sh_command_string(self)?]);
+        if self.is_env_cleared() {
+            wrapped.env_clear();
+        }
+        copy_env_and_current_dir(self, &mut wrapped);
+        Ok(wrapped)
+    }
+
+    /// Returns a command that runs this command on `host` using `ssh host --
+    /// '…'`. The remote login shell is assumed to be POSIX-compatible.
+    ///
+    /// The working directory and env vars are set as part of the remote
+    /// command (`cd … && KEY=value …`), rather than on the returned command. A
+    /// command that clears its environment is run using `env -i KEY=value …`.
+    ///
+    /// This returns an error if `host` starts with `-` (which `ssh` would read
+    /// as an option), if an env var name is not a valid shell variable name,
+    /// or if the command is not valid UTF-8.
+    pub fn wrap_in_ssh(&self, host: &str) -> io::Result<PrintableShellCommand> {
+        if host.starts_with('-') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid host for `ssh`: {:?}", host),
+            ));
+        }
+        let remote_command = if self.is_env_cleared() {
+            let mut command = self.duplicate();
+            command.move_env_into_env_prefix()?;
+            sh_command_string_with_env_and_current_dir(&command)?
+        } else {
+            sh_command_string_with_env_and_current_dir(self)?
+        };
+        let mut wrapped = PrintableShellCommand::new("ssh");
+        wrapped
+            .arg(host)
+            .nested_command_args(["--", &remote_command]);
+        Ok(wrapped)
+    }
+}
+
+/// Wrappers that run the command with a prefix command (e.g. `sudo`).
+///
+/// These replace the program of the underlying `Command`, keeping its args,
+/// working directory, and env vars (which `sudo` and `doas` pass using `env`,
+/// since they reset the environment). (Other configuration of the `Command`, such
+/// as `stdin`/`stdout`/`stderr`, cannot be read and is not kept.) The prefix
+/// command's args and the original program form the first arg group (together
+/// with any earlier wrappers), so the original arg groups are printed as before.
+impl PrintableShellCommand {
+    /// `sudo -- …`
+    pub fn wrap_with_sudo(&mut self) -> io::Result<&mut Self> {
+        self.wrap_with_env_resetting_prefix(OsStr::new("sudo"), vec!["--".into()])
+    }
+
+    /// `sudo -u user -- …`
+    pub fn wrap_with_sudo_as_user<S: AsRef<OsStr>>(&mut self, user: S) -> io::Result<&mut Self> {
+        self.wrap_with_env_resetting_prefix(
+            OsStr::new("sudo"),
+            vec!["-u".into(), user.as_ref().into(), "--".into()],
+        )
+    }
+
+    /// `doas -- …`
+    pub fn wrap_with_doas(&mut self) -> io::Result<&mut Self> {
+        self.wrap_with_env_resetting_prefix(OsStr::new("doas"), vec!["--".into()])
+    }
+
+    /// `doas -u user -- …`
+    pub fn wrap_with_doas_as_user<S: AsRef<OsStr>>(&mut self, user: S) -> io::Result<&mut Self> {
+        self.wrap_with_env_resetting_prefix(
+            OsStr::new("doas"),
+            vec!["-u".into(), user.as_ref().into(), "--".into()],
+        )
+    }
+
+    /// `sudo` and `doas` reset the environment of the command they run, so
+    /// env vars are passed using `env` inside the prefix (e.g. `sudo -- env
+    /// KEY=value …`) instead of being kept on the underlying `Command`. This
+    /// returns an error if `env` cannot express them (see `wrap_with_env(…)`).
+    fn wrap_with_env_resetting_prefix(
+        &mut self,
+        program: &OsStr,
+        prefix_args: Vec<OsString>,
+    ) -> io::Result<&mut Self> {
+        self.move_env_into_env_prefix()?;
+        self.wrap_with_prefix(program, vec![prefix_args], true);
+        Ok(self)
+    }
+
+    /// Replaces the env vars of the command (and `env_clear()`) with an `env
+    /// [-i] [-u KEY]… [KEY=value]… …` prefix. The working directory is kept.
+    /// Does nothing if the command does not change its environment.
+    pub(crate) fn move_env_into_env_prefix(&mut self) -> io::Result<()> {
+        if !self.is_env_cleared() && self.get_envs().next().is_none() {
+            return Ok(());
+        }
+        check_env_program(self.get_program())?;
+        let mut prefix_args: Vec<OsString> = vec![];
+        if self.is_env_cleared() {
+            prefix_args.push("-i".into());
+        }
+        let mut assignments = vec![];
+        for (key, value) in self.get_envs() {
+            check_env_key(key)?;
+            match value {
+                Some(value) => {
+                    let mut assignment = key.to_owned();
+                    assignment.push("=");
+                    assignment.push(value);
+                    assignments.push(assignment);
+                }
+                None if !self.is_env_cleared() => {
+                    prefix_args.extend(["-u".into(), key.to_owned()]);
+                }
+                None => {}
+            }
+        }
+        prefix_args.extend(assignments);
+        let current_dir = self.get_current_dir().map(Path::to_owned);
+        self.wrap_with_prefix(OsStr::new("env"), vec![prefix_args], false);
+        if let Some(current_dir) = current_dir {
+            self.current_dir(current_dir);
+        }
+        Ok(())
+    }
+
+    /// `nice -n adjustment …`
+    pub fn wrap_with_nice(&mut self, adjustment: i32) -> &mut Self {
+        self.wrap_with_prefix(
+            OsStr::new("nice"),
+            vec![vec!["-n".into(), adjustment.to_string().into()]],
+            true,
+        );
+        self
+    }
+
+    /// `ionice -c class [-n level] …`
+    pub fn wrap_with_ionice(&mut self, class: u32, level: Option<u32>) -> &mut Self {
+        let mut prefix_args: Vec<OsString> = vec!["-c".into(), class.to_string().into()];
+        if let Some(level) = level {
+            prefix_args.extend(["-n".into(), level.to_string().into()]);
+        }
+        self.wrap_with_prefix(OsStr::new("ionice"), vec![prefix_args], true);
+        self
+    }
+
+    /// `timeout 30s …` (fractional durations are printed like `1.5s`)
+    ///
+    /// This returns an error if `duration` is zero, since `timeout 0s`
+    /// disables the timeout.
+    pub fn wrap_with_timeout(&mut self, duration: Duration) -> io::Result<&mut Self> {
+        if duration.is_zero() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "`timeout` does not support a zero duration",
+            ));
+        }
+        self.wrap_with_prefix(
+            OsStr::new("timeout"),
+            vec![vec![format_timeout_duration(duration).into()]],
+            true,
+        );
+        Ok(self)
+    }
+
+    /// `env KEY=value … …`
+    ///
+    /// This makes the env vars visible in the printed command. (To set env vars
+    /// without printing them, use `.env(…)` on the underlying `Command`.)
+    ///
+    /// This returns an error if a key is empty, starts with `-`, or contains
+    /// `=`, or if the program starts with `-` or contains `=` (since `env`
+    /// would read it as an option or another assignment).
+    pub fn wrap_with_env<I, K, V>(&mut self, vars: I) -> io::Result<&mut Self>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        check_env_program(self.get_program())?;
+        let mut prefix_args = vec![];
+        for (key, value) in vars {
+            let key = key.as_ref();
+            check_env_key(key)?;
+            let mut assignment = key.to_owned();
+            assignment.push("=");
+            assignment.push(value);
+            prefix_args.push(assignment);
+        }
+        self.wrap_with_prefix(OsStr::new("env"), vec![prefix_args], true);
+        Ok(self)
+    }
+}
+
+/// Formats `duration` for `timeout` without going through floating point,
+/// e.g. `30s` or `1.5s`.
+fn format_timeout_duration(duration: Duration) -> String {
+    let nanos = duration.subsec_nanos();
+    if nanos == 0 {
+        return format!("{}s", duration.as_secs());
+    }
+    let fraction = format!("{:09}", nanos);
+    format!("{}.{}s", duration.as_secs(), fraction.trim_end_matches('0'))
+}
+
+/// `env` reads a leading `-` as an option and `=` as an assignment (even
+/// after `--`), so neither can be used in a program that it runs.
+fn check_env_program(program: &OsStr) -> io::Result<()> {
+    let bytes = program.as_encoded_bytes();
+    if bytes.starts_with(b"-") || bytes.contains(&b'=') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("`env` cannot run this program: {:?}", program),
+        ));
+    }
+    Ok(())
+}
+
+fn check_env_key(key: &OsStr) -> io::Result<()> {
+    let bytes = key.as_encoded_bytes();
+    if bytes.is_empty() || bytes.starts_with(b"-") || bytes.contains(&b'=') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid env var name for `env`: {:?}", key),
+        ));
+    }
+    Ok(())
+}
+
+/// Wrappers that run the command inside a container.
+///
+/// The env vars of the command are passed using `-e KEY=value` and the working
+/// directory using `-w dir`, and are removed from the underlying `Command`
+/// (since they apply inside the container). Removed env vars cannot be
+/// expressed and are dropped. Each of these forms its own arg group, followed
+/// by the container/image and the original program, so the original arg
+/// groups are printed as before.
+///
+/// `-w` requires an absolute path, so these return an error if the working
+/// directory is relative (unless it is mounted, see `wrap_in_container_run`).
+impl PrintableShellCommand {
+    /// `docker exec [-e KEY=value]… [-w dir] container …`
+    pub fn wrap_in_container_exec<S: AsRef<OsStr>>(
+        &mut self,
+        engine: ContainerEngine,
+        container: S,
+    ) -> io::Result<&mut Self> {
+        let mut prefix_arg_groups = vec![vec!["exec".into()]];
+        prefix_arg_groups.extend(self.container_env_and_current_dir_arg_groups(None)?);
+        prefix_arg_groups.push(vec![container.as_ref().to_owned()]);
+        self.wrap_with_prefix(engine.program(), prefix_arg_groups, false);
+        Ok(self)
+    }
+
+    /// `docker run --rm [--mount type=bind,src=dir,dst=dir] [-e KEY=value]…
+    /// [-w dir] image …`
+    ///
+    /// If `mount_current_dir` is set, the working directory of the command (or
+    /// of the current process, if it is not set) is made absolute and mounted
+    /// at the same path inside the container. This returns an error if the
+    /// working directory of the current process cannot be determined, or if
+    /// it contains a `,` or `"` (which `--mount` cannot express portably).
+    pub fn wrap_in_container_run<S: AsRef<OsStr>>(
+        &mut self,
+        engine: ContainerEngine,
+        image: S,
+        mount_current_dir: bool,
+    ) -> io::Result<&mut Self> {
+        let mut prefix_arg_groups = vec![vec!["run".into(), "--rm".into()]];
+        let mounted_dir = if mount_current_dir {
+            let mounted_dir = path::absolute(self.get_current_dir().unwrap_or(Path::new(".")))?;
+            if mounted_dir
+                .as_os_str()
+                .as_encoded_bytes()
+                .iter()
+                .any(|byte| matches!(byte, b',' | b'"'))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "Cannot bind-mount a path containing `,` or `\"`: {:?}",
+                        mounted_dir
+                    ),
+                ));
+            }
+            let mut mount = OsString::from("type=bind,src=");
+            mount.push(&mounted_dir);
+            mount.push(",dst=");
+            mount.push(&mounted_dir);
+            prefix_arg_groups.push(vec!["--mount".into(), mount]);
+            Some(mounted_dir)
+        } else {
+            None
+        };
+        prefix_arg_groups
+            .extend(self.container_env_and_current_dir_arg_groups(mounted_dir.as_deref())?);
+        prefix_arg_groups.push(vec![image.as_ref().to_owned()]);
+        self.wrap_with_prefix(engine.program(), prefix_arg_groups, false);
+        Ok(self)
+    }
+
+    fn container_env_and_current_dir_arg_groups(
+        &self,
+        current_dir_override: Option<&Path>,
+    ) -> io::Result<Vec<Vec<OsString>>> {
+        let mut arg_groups: Vec<Vec<OsString>> = self
+            .get_envs()
+            .filter_map(|(key, value)| {
+                let mut assignment = key.to_owned();
+                assignment.push("=");
+                assignment.push(value?);
+                Some(vec!["-e".into(), assignment])
+            })
+            .collect();
+        if let Some(current_dir) = current_dir_override.or(self.get_current_dir()) {
+            if !current_dir.is_absolute() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "The working directory in a container must be absolute: {:?}",
+                        current_dir
+                    ),
+                ));
+            }
+            arg_groups.push(vec!["-w".into(), current_dir.into()]);
+        }
+        Ok(arg_groups)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::Utf8Error;
+
+    use std::time::Duration;
+
+    use super::format_timeout_duration;
+    use crate::{
+        ContainerEngine, FormattingOptions, PrintableShellCommand, ShellPrintable,
+        ShellPrintableWithOptions,
+    };
+
+    fn ffmpeg_command_for_testing() -> PrintableShellCommand {
+        let mut printable_shell_command = PrintableShellCommand::new("ffmpeg");
+        printable_shell_command
+            .args_with_comment(["-i", "./test/My video.mp4"], "input")
+            .args(["-filter:v", "setpts=2.0*PTS"])
+            .arg("./test/It's slow-mo.mov");
+        printable_shell_command
+    }
+
+    #[test]
+    fn sh_c() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = ffmpeg_command_for_testing();
+        printable_shell_command
+            .current_dir("/tmp")
+            .env("FFREPORT", "1");
+        let wrapped = printable_shell_command.wrap_in_sh_c()?;
+        assert_eq!(
+            wrapped.printable_invocation_string()?,
+            r#"sh \
+  -c 'ffmpeg -i '\''./test/My video.mp4'\'' -filter:v '\''setpts=2.0*PTS'\'' "./test/It'\''s slow-mo.mov"'"#
+        );
+        assert_eq!(
+            wrapped.get_args().collect::<Vec<_>>(),
+            [
+                "-c",
+                r#"ffmpeg -i './test/My video.mp4' -filter:v 'setpts=2.0*PTS' "./test/It's slow-mo.mov""#
+            ]
+        );
+        assert_eq!(wrapped.get_current_dir(), Some("/tmp".as_ref()));
+        assert_eq!(
+            wrapped.get_envs().collect::<Vec<_>>(),
+            [("FFREPORT".as_ref(), Some("1".as_ref()))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bash_c_prefers_double_quotes() -> Result<(), Utf8Error> {
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.args(["hello", "wide world"]);
+        assert_eq!(
+            printable_shell_command
+                .wrap_in_bash_c()?
+                .printable_invocation_string()?,
+            r#"bash \
+  -c "echo hello 'wide world'""#
+        );
+
+        // Other args, and other dialects, are quoted as usual.
+        let mut printable_shell_command = PrintableShellCommand::new("echo");
+        printable_shell_command.arg("it's");
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"echo \
+  'it'\''s'"#
+        );
+        assert_eq!(
+            printable_shell_command
+                .wrap_in_bash_c()?
+                .printable_invocation_string_with_options(
+                    FormattingOptions::compact().dialect(crate::ShellDialect::PowerShell)
+                )?,
+            "bash -c 'echo \"it''s\"'"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ssh() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.args(["-l", "My Documents"]);
+        printable_shell_command
+            .current_dir("/home/me")
+            .env("LC_ALL", "C")
+            .env_remove("CLICOLOR");
+        let wrapped = printable_shell_command.wrap_in_ssh("example.com")?;
+        assert_eq!(
+            wrapped.printable_invocation_string()?,
+            r#"ssh \
+  example.com \
+  -- "cd /home/me && unset CLICOLOR && LC_ALL=C ls -l 'My Documents'""#
+        );
+        assert_eq!(wrapped.get_current_dir(), None);
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.current_dir("-v");
+        assert_eq!(
+            printable_shell_command
+                .wrap_in_ssh("example.com")?
+                .printable_invocation_string()?,
+            r#"ssh \
+  example.com \
+  -- 'cd -- -v && ls'"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn ssh_rejects_options_and_invalid_env_var_names() {
+        let printable_shell_command = PrintableShellCommand::new("ls");
+        assert!(printable_shell_command
+            .wrap_in_ssh("-oProxyCommand=touch /tmp/pwned")
+            .is_err());
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.env("A=B; rm -rf ~; X", "1");
+        assert!(printable_shell_command.wrap_in_ssh("example.com").is_err());
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.env_remove("$(reboot)");
+        assert!(printable_shell_command.wrap_in_ssh("example.com").is_err());
+    }
+
+    #[test]
+    fn prefix_wrappers() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = ffmpeg_command_for_testing();
+        printable_shell_command.current_dir("/tmp");
+        printable_shell_command
+            .wrap_with_env([("FFREPORT", "file=report.log")])?
+            .wrap_with_nice(10)
+            .wrap_with_timeout(Duration::from_secs(30))?
+            .wrap_with_sudo_as_user("media")?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"sudo \
+  -u media -- timeout 30s nice -n 10 env FFREPORT=file=report.log ffmpeg \
+  -i './test/My video.mp4' `# input` \
+  -filter:v 'setpts=2.0*PTS' \
+  './test/It'\''s slow-mo.mov'"#
+        );
+        assert_eq!(
+            printable_shell_command.get_args().collect::<Vec<_>>(),
+            [
+                "-u",
+                "media",
+                "--",
+                "timeout",
+                "30s",
+                "nice",
+                "-n",
+                "10",
+                "env",
+                "FFREPORT=file=report.log",
+                "ffmpeg",
+                "-i",
+                "./test/My video.mp4",
+                "-filter:v",
+                "setpts=2.0*PTS",
+                "./test/It's slow-mo.mov"
+            ]
+        );
+        assert_eq!(
+            printable_shell_command.get_current_dir(),
+            Some("/tmp".as_ref())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn sudo_passes_env_vars_inside() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("printenv");
+        printable_shell_command.arg("FOO");
+        printable_shell_command
+            .current_dir("/tmp")
+            .env("FOO", "bar")
+            .env_remove("TERM");
+        printable_shell_command.wrap_with_sudo()?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"sudo \
+  -- env -u TERM FOO=bar printenv \
+  FOO"#
+        );
+        assert_eq!(printable_shell_command.get_envs().count(), 0);
+        assert_eq!(
+            printable_shell_command.get_current_dir(),
+            Some("/tmp".as_ref())
+        );
+        Ok(())
+    }
+
+    /// Runs the wrapped command with a stand-in for `sudo` that resets the
+    /// environment (like `env_reset`).
+    #[cfg(unix)]
+    #[test]
+    fn sudo_env_vars_reach_the_program() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("printenv");
+        printable_shell_command.arg("FOO").env("FOO", "bar");
+        printable_shell_command.wrap_with_doas_as_user("root")?;
+        let output = std::process::Command::new("sh")
+            .args([
+                "-c",
+                r#"while [ "$1" != -- ]; do shift; done; shift; exec env -i PATH=/usr/bin:/bin "$@""#,
+                "doas",
+            ])
+            .args(printable_shell_command.get_args())
+            .envs(
+                printable_shell_command
+                    .get_envs()
+                    .filter_map(|(key, value)| Some((key, value?))),
+            )
+            .output()?;
+        assert_eq!(output.stdout, b"bar\n");
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_wrappers_with_skip_line_wrap_before_first_arg(
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("apt");
+        printable_shell_command
+            .arg("install")
+            .args(["-y", "ffmpeg"]);
+        printable_shell_command
+            .wrap_with_ionice(2, Some(7))
+            .wrap_with_doas()?
+            .wrap_with_timeout(Duration::from_millis(1500))?;
+        assert_eq!(
+            printable_shell_command
+                .printable_invocation_string_with_options(FormattingOptions::ci_log())?,
+            r#"timeout 1.5s doas -- ionice -c 2 -n 7 apt \
+  install \
+  -y ffmpeg"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn env_clear() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command
+            .env_clear()
+            .env("LC_ALL", "C")
+            .current_dir("/tmp");
+        assert!(printable_shell_command
+            .wrap_in_shell_c("sh")?
+            .is_env_cleared());
+        assert_eq!(
+            printable_shell_command
+                .wrap_in_ssh("example.com")?
+                .printable_invocation_string()?,
+            r#"ssh \
+  example.com \
+  -- 'cd /tmp && env -i LC_ALL=C ls'"#
+        );
+        printable_shell_command.wrap_with_nice(10);
+        assert!(printable_shell_command.is_env_cleared());
+        printable_shell_command.wrap_with_sudo()?;
+        assert!(!printable_shell_command.is_env_cleared());
+        assert_eq!(printable_shell_command.get_envs().count(), 0);
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"sudo \
+  -- env -i LC_ALL=C nice -n 10 ls"#
+        );
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.env_clear();
+        printable_shell_command.wrap_in_container_exec(ContainerEngine::Docker, "box")?;
+        assert!(!printable_shell_command.is_env_cleared());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_timeout_and_env() {
+        let mut printable_shell_command = PrintableShellCommand::new("./a=b");
+        assert!(printable_shell_command
+            .wrap_with_timeout(Duration::ZERO)
+            .is_err());
+        assert!(printable_shell_command
+            .wrap_with_env([("LC_ALL", "C")])
+            .is_err());
+
+        let mut printable_shell_command = PrintableShellCommand::new("-ls");
+        assert!(printable_shell_command
+            .wrap_with_env([("LC_ALL", "C")])
+            .is_err());
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        assert!(printable_shell_command
+            .wrap_with_env([("A=B", "C")])
+            .is_err());
+        assert!(printable_shell_command
+            .wrap_with_env([("-i", "C")])
+            .is_err());
+        assert_eq!(printable_shell_command.get_program(), "ls");
+    }
+
+    #[test]
+    fn timeout_durations() {
+        assert_eq!(format_timeout_duration(Duration::from_secs(30)), "30s");
+        assert_eq!(format_timeout_duration(Duration::from_millis(1500)), "1.5s");
+        assert_eq!(format_timeout_duration(Duration::from_millis(250)), "0.25s");
+        assert_eq!(
+            format_timeout_duration(Duration::from_nanos(1_000_000_001)),
+            "1.000000001s"
+        );
+    }
+
+    #[test]
+    fn container_exec() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = ffmpeg_command_for_testing();
+        printable_shell_command
+            .current_dir("/data")
+            .env("FFREPORT", "1")
+            .env_remove("TERM");
+        printable_shell_command.wrap_in_container_exec(ContainerEngine::Docker, "media-worker")?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"docker \
+  exec \
+  -e FFREPORT=1 \
+  -w /data \
+  media-worker ffmpeg \
+  -i './test/My video.mp4' `# input` \
+  -filter:v 'setpts=2.0*PTS' \
+  './test/It'\''s slow-mo.mov'"#
+        );
+        assert_eq!(printable_shell_command.get_current_dir(), None);
+        assert_eq!(printable_shell_command.get_envs().count(), 0);
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.current_dir("data");
+        assert!(printable_shell_command
+            .wrap_in_container_exec(ContainerEngine::Docker, "media-worker")
+            .is_err());
+        assert!(printable_shell_command
+            .wrap_in_container_run(ContainerEngine::Docker, "alpine", false)
+            .is_err());
+        assert_eq!(printable_shell_command.get_program(), "ls");
+        Ok(())
+    }
+
+    #[test]
+    fn container_run() -> Result<(), Box<dyn std::error::Error>> {
+        let mut printable_shell_command = PrintableShellCommand::new("cargo");
+        printable_shell_command
+            .arg("build")
+            .args(["--profile", "release"]);
+        printable_shell_command.current_dir("/src/app");
+        printable_shell_command
+            .wrap_in_container_run(ContainerEngine::Podman, "rust:latest", true)?
+            .wrap_with_timeout(Duration::from_secs(600))?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"timeout \
+  600s podman run --rm \
+  --mount type=bind,src=/src/app,dst=/src/app \
+  -w /src/app \
+  rust:latest cargo \
+  build \
+  --profile release"#
+        );
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.wrap_in_container_run(ContainerEngine::Docker, "alpine", false)?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"docker \
+  run --rm \
+  alpine ls"#
+        );
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.current_dir("/srv/a:b");
+        printable_shell_command
+            .wrap_in_container_run(ContainerEngine::Docker, "alpine", true)?
+            .wrap_with_sudo()?;
+        assert_eq!(
+            printable_shell_command.printable_invocation_string()?,
+            r#"sudo \
+  -- docker run --rm \
+  --mount type=bind,src=/srv/a:b,dst=/srv/a:b \
+  -w /srv/a:b \
+  alpine ls"#
+        );
+
+        let mut printable_shell_command = PrintableShellCommand::new("ls");
+        printable_shell_command.current_dir("/srv/a,b");
+        assert!(printable_shell_command
+            .wrap_in_container_run(ContainerEngine::Docker, "alpine", true)
+            .is_err());
+        Ok(())
+    }
+}